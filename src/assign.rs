@@ -0,0 +1,75 @@
+use crate::db::Owner;
+use teloxide::types::{Message, MessageEntityKind, User};
+use teloxide::utils::html;
+
+/// The admin as the owner of a topic, named by the username if there is one.
+pub fn owner_of(admin: &User) -> Owner {
+    let name = match &admin.username {
+        Some(username) => format!("@{username}"),
+        None => admin.full_name(),
+    };
+    Owner { id: Some(admin.id.0 as i64), name }
+}
+
+/// Returns the admin mentioned in `/assign`.
+/// Admins without a username are mentioned by the name, then the id is known too.
+pub fn assignee(msg: &Message) -> Option<Owner> {
+    msg.parse_entities()?.into_iter().find_map(|entity| match entity.kind() {
+        MessageEntityKind::TextMention { user } => Some(owner_of(user)),
+        MessageEntityKind::Mention => Some(Owner { id: None, name: entity.text().to_string() }),
+        _ => None,
+    })
+}
+
+/// Returns `true` if the topic is assigned to the admin, by the id or by the username.
+pub fn is_owner(owner: &Owner, admin: &User) -> bool {
+    match owner.id {
+        Some(id) => id == admin.id.0 as i64,
+        None => admin.username.as_ref()
+            .is_some_and(|username| owner.name.eq_ignore_ascii_case(&format!("@{username}"))),
+    }
+}
+
+/// Mentions the owner so that Telegram notifies the admin.
+pub fn mention(owner: &Owner) -> String {
+    match owner.id {
+        Some(id) if !owner.name.starts_with('@') => {
+            format!("<a href=\"tg://user?id={id}\">{}</a>", html::escape(&owner.name))
+        },
+        _ => html::escape(&owner.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use teloxide::types::UserId;
+
+    fn admin(username: Option<&str>) -> User {
+        User {
+            id: UserId(7),
+            is_bot: false,
+            first_name: "Alice".to_string(),
+            last_name: Some("<Smith>".to_string()),
+            username: username.map(str::to_string),
+            language_code: None,
+            is_premium: false,
+            added_to_attachment_menu: false,
+        }
+    }
+
+    #[test]
+    fn test_owners() {
+        let alice = owner_of(&admin(Some("alice")));
+        assert_eq!(alice, Owner { id: Some(7), name: "@alice".to_string() });
+        assert_eq!(mention(&alice), "@alice");
+        let nameless = owner_of(&admin(None));
+        assert_eq!(mention(&nameless), "<a href=\"tg://user?id=7\">Alice &lt;Smith&gt;</a>");
+
+        let by_username = Owner { id: None, name: "@Alice".to_string() };
+        assert!(is_owner(&alice, &admin(None)));
+        assert!(is_owner(&by_username, &admin(Some("alice"))));
+        assert!(!is_owner(&by_username, &admin(None)));
+        assert!(!is_owner(&Owner { id: Some(8), ..alice }, &admin(Some("alice"))));
+    }
+}
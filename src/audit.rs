@@ -0,0 +1,102 @@
+use crate::db::AuditEntry;
+use crate::signature::MAX_TEXT;
+use crate::utils::{format_date, topic_link};
+use teloxide::types::ChatId;
+use teloxide::utils::html;
+
+/// Arguments are free text, f.e. the names of dropped topics, so only their start is shown.
+const MAX_ARGS: usize = 64;
+
+/// Formats the recent entries of the audit log for `/audit`.
+/// The entries that do not fit into a message are left out, the export has all of them.
+pub fn log_text(entries: &[AuditEntry]) -> String {
+    if entries.is_empty() {
+        return "📜 The audit log is empty".to_string();
+    }
+    let mut text = "📜 Recent actions:".to_string();
+    for (i, entry) in entries.iter().enumerate() {
+        let line = format!("\n• {}", entry_text(entry));
+        // The tags are counted too, which is stricter than Telegram, and room is left for the last line
+        if text.chars().count() + line.chars().count() > MAX_TEXT - 32 {
+            text.push_str(&format!("\n• … {} more", entries.len() - i));
+            break;
+        }
+        text.push_str(&line);
+    }
+    text
+}
+
+/// `2024-01-03 12:00 UTC Alice: ban 123456789 in the topic, f.e. 30d`
+fn entry_text(entry: &AuditEntry) -> String {
+    let mut text = format!(
+        "{} {}: {}",
+        format_date(entry.ts),
+        html::escape(&entry.actor_name),
+        entry.action.as_str(),
+    );
+    if let Some(target) = entry.target {
+        text.push_str(&format!(" <code>{target}</code>"));
+    }
+    if let Some(thread_id) = entry.thread_id {
+        text.push_str(&format!(" in <a href=\"{}\">the topic</a>", topic_link(ChatId(entry.forum_id), thread_id)));
+    }
+    if let Some(args) = &entry.args {
+        let args = match args.char_indices().nth(MAX_ARGS) {
+            Some((end, _)) => format!("{}…", &args[..end]),
+            None => args.clone(),
+        };
+        text.push_str(&format!(", {}", html::escape(&args)));
+    }
+    text
+}
+
+/// Serializes the entry as a line of the export.
+pub fn to_json(entry: &AuditEntry) -> serde_json::Value {
+    serde_json::json!({
+        "actor_id": entry.actor_id,
+        "actor_name": entry.actor_name,
+        "action": entry.action.as_str(),
+        "target": entry.target,
+        "forum_id": entry.forum_id,
+        "thread_id": entry.thread_id,
+        "args": entry.args,
+        "ts": entry.ts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::AuditAction;
+
+    #[test]
+    fn test_log_text() {
+        let entry = AuditEntry {
+            actor_id: 7,
+            actor_name: "Alice <A>".to_string(),
+            action: AuditAction::Role,
+            target: None,
+            forum_id: -100123,
+            thread_id: None,
+            args: Some("@bob observer".to_string()),
+            ts: 1_704_283_200,
+        };
+        assert_eq!(log_text(&[]), "📜 The audit log is empty");
+        assert_eq!(
+            log_text(std::slice::from_ref(&entry)),
+            "📜 Recent actions:\n• 2024-01-03 12:00 UTC Alice &lt;A&gt;: role, @bob observer",
+        );
+        let entry = AuditEntry { action: AuditAction::Ban, target: Some(42), thread_id: Some(3), args: None, ..entry };
+        assert_eq!(
+            entry_text(&entry),
+            "2024-01-03 12:00 UTC Alice &lt;A&gt;: ban <code>42</code> in <a href=\"https://t.me/c/123/3\">the topic</a>",
+        );
+        assert_eq!(to_json(&entry)["action"], "ban");
+
+        let entry = AuditEntry { action: AuditAction::DropTopic, args: Some("a".repeat(1000)), ..entry };
+        assert!(entry_text(&entry).ends_with(&format!(", {}…", "a".repeat(MAX_ARGS))));
+        let text = log_text(&vec![entry; 100]);
+        assert!(text.chars().count() <= MAX_TEXT);
+        assert!(text.ends_with(" more"));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::db::{Database, Intake, UserProfile};
+use crate::errors;
+use crate::intake;
+use crate::utils::format_date;
+use crate::Bot;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, LinkPreviewOptions, MessageId, ThreadId, User};
+use teloxide::utils::html;
+
+pub const TOPIC_ICON_COLOR: [u32; 6] = [  // https://core.telegram.org/bots/api#createforumtopic
+    7322096, 16766590, 13338331, 9367192, 16749490, 16478047,
+];
+
+/// Topic names are limited to 128 characters.
+const MAX_TOPIC_NAME: usize = 128;
+
+pub const LINK_PREVIEW_OPTIONS: LinkPreviewOptions = LinkPreviewOptions {
+    is_disabled: true,
+    url: None,
+    prefer_small_media: false,
+    prefer_large_media: false,
+    show_above_text: false,
+};
+
+/// Builds the profile of the user writing in the topic, the counters start from zero.
+pub fn profile_of(user: &User, forum_id: i64, thread_id: i32, timestamp: i64) -> UserProfile {
+    UserProfile {
+        chat_id: user.id.0 as i64,
+        forum_id,
+        thread_id,
+        full_name: user.full_name(),
+        username: user.username.clone(),
+        language_code: user.language_code.clone(),
+        first_seen: timestamp,
+        last_seen: timestamp,
+        messages_in: 0,
+        messages_out: 0,
+        card_message_id: None,
+        source: None,
+        intake: None,
+        owner: None,
+    }
+}
+
+pub fn ban_button() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(
+        vec![vec![InlineKeyboardButton::callback("🚫 Ban", "ban")]]
+    )
+}
+
+/// The pinned card: only the fields that change rarely, so it is edited only when the user changes them.
+pub fn card_text(profile: &UserProfile) -> String {
+    let url = match &profile.username {
+        Some(username) => format!("https://t.me/{username}"),
+        None => format!("tg://user?id={}", profile.chat_id),
+    };
+    let mut text = format!(
+        "<a href=\"{url}\"><b>{}</b></a> \
+        \n🆔 <code>{}</code> \
+        \n🎗 Username - {} \
+        \n\n🌐 Language code: {} \
+        \n📅 First seen: {}",
+        html::escape(&profile.full_name),
+        profile.chat_id,
+        profile.username.as_deref().unwrap_or("None"),
+        profile.language_code.as_deref().map(html::escape).unwrap_or_else(|| "None".to_string()),
+        format_date(profile.first_seen),
+    );
+    if let Some(source) = &profile.source {
+        text.push_str(&format!("\n🔗 Source: <code>{}</code>", html::escape(source)));
+    }
+    if let Some(owner) = &profile.owner {
+        text.push_str(&format!("\n👤 Assigned to: {}", html::escape(&owner.name)));
+    }
+    if let Some(intake) = &profile.intake {
+        text.push_str("\n\n");
+        text.push_str(&intake::intake_text(intake));
+    }
+    text
+}
+
+/// The topic is named after the first name of the user and the category of the intake form.
+pub fn topic_name(first_name: &str, intake: Option<&Intake>) -> String {
+    let name = match intake {
+        Some(intake) => format!("{first_name} | {}", intake.category),
+        None => first_name.to_string(),
+    };
+    name.chars().take(MAX_TOPIC_NAME).collect()
+}
+
+/// The card with the activity of the user, for `/info`.
+///
+/// # Arguments
+///
+/// * `bans` - Timestamps of the user's bans.
+pub fn info_text(profile: &UserProfile, bans: &[i64]) -> String {
+    let bans = match bans.last() {
+        Some(last) => format!("{}, the last one on {}", bans.len(), format_date(*last)),
+        None => "none".to_string(),
+    };
+    format!(
+        "{} \
+        \n👁 Last seen: {} \
+        \n📥 Messages in: {} \
+        \n📤 Messages out: {} \
+        \n🚫 Bans: {bans}",
+        card_text(profile),
+        format_date(profile.last_seen),
+        profile.messages_in,
+        profile.messages_out,
+    )
+}
+
+/// Edits the pinned card and the topic title after the user changed the profile.
+///
+/// # Arguments
+///
+/// * `previous` - The profile before the change.
+/// * `profile` - The new profile.
+/// * `first_name` - The topic is named after the first name of the user.
+pub async fn refresh(
+    bot: &Bot,
+    db: &Database,
+    previous: &UserProfile,
+    first_name: &str,
+) -> errors::Result<()> {
+    let Some(profile) = db.get_user(previous.chat_id).await? else {
+        return Ok(());
+    };
+    edit(bot, &profile).await?;
+    if previous.full_name != profile.full_name {
+        bot.edit_forum_topic(ChatId(profile.forum_id), ThreadId(MessageId(profile.thread_id)))
+            .name(topic_name(first_name, profile.intake.as_ref()))
+            .await?;
+    }
+    tracing::info!("User card refreshed: {}", profile.thread_id);
+
+    Ok(())
+}
+
+/// Edits the pinned card, if it is known.
+pub async fn edit(bot: &Bot, profile: &UserProfile) -> errors::Result<()> {
+    if let Some(card_message_id) = profile.card_message_id {
+        bot.edit_message_text(ChatId(profile.forum_id), MessageId(card_message_id), card_text(profile))
+            .reply_markup(ban_button())
+            .link_preview_options(LINK_PREVIEW_OPTIONS)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{profile, Owner};
+
+    #[test]
+    fn test_info_text() {
+        let profile = UserProfile {
+            thread_id: 2,
+            full_name: "John <Doe>".to_string(),
+            language_code: Some("en".to_string()),
+            first_seen: 1_704_283_200,
+            last_seen: 1_704_286_800,
+            messages_in: 5,
+            messages_out: 3,
+            ..profile(1)
+        };
+
+        let card = card_text(&profile);
+        assert!(card.starts_with("<a href=\"tg://user?id=1\"><b>John &lt;Doe&gt;</b></a>"));
+        assert!(card.ends_with("📅 First seen: 2024-01-03 12:00 UTC"));
+        let info = info_text(&profile, &[1_704_286_800]);
+        assert!(info.contains("👁 Last seen: 2024-01-03 13:00 UTC"));
+        assert!(info.contains("📥 Messages in: 5"));
+        assert!(info.ends_with("🚫 Bans: 1, the last one on 2024-01-03 13:00 UTC"));
+        assert!(info_text(&profile, &[]).ends_with("🚫 Bans: none"));
+        let profile = UserProfile { source: Some("ads".to_string()), ..profile };
+        assert!(card_text(&profile).ends_with("\n🔗 Source: <code>ads</code>"));
+        let owner = Owner { id: Some(7), name: "@alice".to_string() };
+        let profile = UserProfile { owner: Some(owner), ..profile };
+        assert!(card_text(&profile).ends_with("<code>ads</code>\n👤 Assigned to: @alice"));
+        let intake = Intake { category: "Billing".to_string(), answers: vec![] };
+        let profile = UserProfile { intake: Some(intake.clone()), ..profile };
+        assert!(card_text(&profile).ends_with("@alice\n\n📋 Category: Billing"));
+        assert_eq!(topic_name("John", Some(&intake)), "John | Billing");
+        assert_eq!(topic_name(&"a".repeat(200), None).chars().count(), 128);
+    }
+}
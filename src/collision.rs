@@ -0,0 +1,92 @@
+use crate::config::Settings;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId};
+use teloxide::utils::html;
+
+/// Prefix of the callback data of the buttons under a held message.
+pub const CALLBACK_PREFIX: &str = "lock:";
+pub const CALLBACK_SEND: &str = "lock:send";
+pub const CALLBACK_DISCARD: &str = "lock:discard";
+
+/// The topic is locked by the first admin who replies, until the admin is silent for `window` seconds.
+/// Messages of other admins are relayed with a warning, or held until confirmed if `hold` is set.
+#[derive(Clone, Copy, Debug)]
+pub struct ReplyLock {
+    pub window: u64,
+    pub hold: bool,
+}
+
+impl ReplyLock {
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        settings.reply_lock_window.map(|window| Self { window, hold: settings.reply_lock_hold })
+    }
+}
+
+/// What the author chose for the held message.
+/// `reply_to` is the message in the topic the held message replies to, Telegram does not send it with the callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Choice {
+    Send { reply_to: Option<MessageId> },
+    Discard,
+}
+
+impl Choice {
+    pub fn from_callback(data: &str) -> Option<Self> {
+        if data == CALLBACK_DISCARD {
+            return Some(Choice::Discard);
+        }
+        match data.strip_prefix(CALLBACK_SEND)? {
+            "" => Some(Choice::Send { reply_to: None }),
+            reply_to => reply_to.strip_prefix(':')?.parse().ok().map(|id| Choice::Send { reply_to: Some(MessageId(id)) }),
+        }
+    }
+}
+
+/// `reply_to` is kept in the callback data of the send button.
+pub fn keyboard(reply_to: Option<MessageId>) -> InlineKeyboardMarkup {
+    let send = match reply_to {
+        Some(reply_to) => format!("{CALLBACK_SEND}:{}", reply_to.0),
+        None => CALLBACK_SEND.to_string(),
+    };
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("📨 Send anyway", send),
+        InlineKeyboardButton::callback("🗑 Discard", CALLBACK_DISCARD),
+    ]])
+}
+
+/// The notice under a message of another admin.
+///
+/// # Arguments
+///
+/// * `holder` - Name of the admin who is replying.
+/// * `held` - The message is not relayed until confirmed.
+pub fn notice_text(holder: &str, held: bool) -> String {
+    let holder = html::escape(holder);
+    if held {
+        format!("✋ {holder} is replying to this user, the message is not sent yet")
+    } else {
+        format!("⚠️ {holder} is replying to this user too")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notice_text() {
+        assert_eq!(notice_text("Alice <A>", false), "⚠️ Alice &lt;A&gt; is replying to this user too");
+        assert_eq!(notice_text("Alice", true), "✋ Alice is replying to this user, the message is not sent yet");
+    }
+
+    #[test]
+    fn test_choice() {
+        let send = |markup: InlineKeyboardMarkup| match &markup.inline_keyboard[0][0].kind {
+            teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => Choice::from_callback(data),
+            _ => None,
+        };
+        assert_eq!(send(keyboard(None)), Some(Choice::Send { reply_to: None }));
+        assert_eq!(send(keyboard(Some(MessageId(42)))), Some(Choice::Send { reply_to: Some(MessageId(42)) }));
+        assert_eq!(Choice::from_callback(CALLBACK_DISCARD), Some(Choice::Discard));
+        assert_eq!(Choice::from_callback("lock:send:soon"), None);
+    }
+}
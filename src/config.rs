@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use config::{Config, Environment};
 use serde::Deserialize;
@@ -5,6 +6,81 @@ use url::Url;
 use teloxide::types::ChatId;
 use secrecy::SecretBox;
 use crate::errors::ConfigError;
+use crate::roles::Role;
+use crate::routing;
+use crate::sla;
+use crate::utils::parse_duration;
+
+/// Output format of the logs.
+#[derive(Deserialize, Default, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+/// How often the digest is posted.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestPeriod {
+    Daily,
+    Weekly,
+}
+
+/// Which replies of admins are signed with the name.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureMode {
+    #[default]
+    Off,
+    /// Every admin, with the alias or the full name.
+    Always,
+    /// Only the admins who set an alias with `/alias`.
+    OptIn,
+}
+
+/// Where the name of the admin goes in a signed reply.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignaturePosition {
+    Prefix,
+    #[default]
+    Suffix,
+}
+
+/// Where the rate limit buckets are kept.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStore {
+    /// Per instance of the bot.
+    #[default]
+    Memory,
+    /// Shared between the instances of the bot.
+    Redis,
+}
+
+/// Welcome texts for the deep-link payloads, read from `START_COMMAND_{PAYLOAD}`.
+#[derive(Clone, Debug, Default)]
+pub struct StartTexts(HashMap<String, String>);
+
+impl StartTexts {
+    const PREFIX: &'static str = "START_COMMAND_";
+
+    fn from_vars(vars: impl Iterator<Item = (String, String)>) -> Self {
+        let texts = vars
+            .filter_map(|(name, text)| Some((name.strip_prefix(Self::PREFIX)?.to_string(), text)))
+            .collect();
+        Self(texts)
+    }
+
+    /// Returns the text for the payload, f.e. `START_COMMAND_ADS` for `ads`.
+    pub fn get(&self, payload: &str) -> Option<&str> {
+        self.0
+            .get(&payload.to_ascii_uppercase().replace('-', "_"))
+            .map(String::as_str)
+    }
+}
 
 #[derive(Deserialize)]
 pub struct Settings {
@@ -14,6 +90,101 @@ pub struct Settings {
     pub redis_url: Url,
     pub webhook_url: Option<Url>,
     pub webhook_listener: Option<SocketAddr>,
+    pub http_listener: Option<SocketAddr>,
+    #[serde(default)]
+    pub log_format: LogFormat,
+    pub otlp_endpoint: Option<Url>,
+    /// Seconds after which dirty mappings are written to SQLite.
+    #[serde(default = "default_sync_interval")]
+    pub sync_interval: u64,
+    /// Number of dirty mappings that are written to SQLite right away.
+    #[serde(default = "default_sync_batch_size")]
+    pub sync_batch_size: usize,
+    /// Seconds to wait for the handlers, and then for the scheduler, on shutdown.
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    /// Comma-separated waiting times after which unanswered messages are escalated, f.e. `15m,1h,4h`.
+    pub sla_thresholds: Option<String>,
+    /// Topic of the default forum for the alerts about unanswered messages of its users.
+    pub sla_alerts_thread: Option<i32>,
+    /// On-duty admins mentioned on the last escalation step, f.e. `@alice @bob`.
+    pub sla_mentions: Option<String>,
+    /// Seconds between the checks of unanswered messages.
+    #[serde(default = "default_sla_check_interval")]
+    pub sla_check_interval: u64,
+    /// The digest is not posted if the period is not set.
+    pub digest_period: Option<DigestPeriod>,
+    /// Topic of the default forum for the digest, General by default.
+    /// The other forums always get the digest in General.
+    pub digest_thread: Option<i32>,
+    /// Path to the JSON file with the intake form, topics are created right away if it is not set.
+    pub intake_form: Option<String>,
+    /// Comma-separated routes of new users to other forums, f.e. `language:ru=-100123,source:ads=-100456`.
+    pub forum_routes: Option<String>,
+    /// Comma-separated forums the new topics overflow to when the routed forum is full.
+    pub overflow_forums: Option<String>,
+    /// Open topics per forum after which the next forum of the overflow pool is used.
+    pub max_open_topics: Option<i64>,
+    /// Seconds a replying admin keeps the topic, collisions are not detected if it is not set.
+    pub reply_lock_window: Option<u64>,
+    /// Hold the messages of other admins until they are confirmed, instead of only warning.
+    #[serde(default)]
+    pub reply_lock_hold: bool,
+    #[serde(default)]
+    pub signature_mode: SignatureMode,
+    #[serde(default)]
+    pub signature_position: SignaturePosition,
+    /// Role of the members who have no role set with `/role` and are not admins of the forum.
+    #[serde(default = "default_role")]
+    pub default_role: Role,
+    /// Messages a user can send at once, the messages of users are not limited if it is not set.
+    pub rate_limit_burst: Option<u32>,
+    /// Time after which a user can send one more message, f.e. `3s`.
+    #[serde(default = "default_rate_limit_refill")]
+    pub rate_limit_refill: String,
+    /// Messages over the limit after which the user is muted.
+    #[serde(default = "default_rate_limit_violations")]
+    pub rate_limit_violations: u32,
+    /// For how long a flooding user is muted, f.e. `1h`.
+    #[serde(default = "default_rate_limit_mute")]
+    pub rate_limit_mute: String,
+    #[serde(default)]
+    pub rate_limit_store: RateLimitStore,
+    #[serde(skip)]
+    pub start_texts: StartTexts,
+}
+
+fn default_sync_interval() -> u64 {
+    60
+}
+
+fn default_sync_batch_size() -> usize {
+    500
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn default_sla_check_interval() -> u64 {
+    60
+}
+
+/// Members are only trusted with the users once the role is given to them.
+fn default_role() -> Role {
+    Role::Observer
+}
+
+fn default_rate_limit_refill() -> String {
+    "3s".to_string()
+}
+
+fn default_rate_limit_violations() -> u32 {
+    5
+}
+
+fn default_rate_limit_mute() -> String {
+    "1h".to_string()
 }
 
 impl Settings {
@@ -23,12 +194,45 @@ impl Settings {
             .add_source(Environment::default())
             .build()?;
 
-        let settings: Settings = config.try_deserialize()?;
+        let mut settings: Settings = config.try_deserialize()?;
+        settings.start_texts = StartTexts::from_vars(std::env::vars());
         if settings.webhook_url.is_some() && settings.webhook_listener.is_none() {
             return Err(ConfigError::Invalid(
                 "WEBHOOK_URL is set, but the address that the bot will listen to (WEBHOOK_LISTENER) is not"
             ));
         }
+        if settings.webhook_url.is_some()
+            && settings.http_listener.is_some()
+            && settings.http_listener == settings.webhook_listener
+        {
+            return Err(ConfigError::Invalid(
+                "HTTP_LISTENER must differ from WEBHOOK_LISTENER, the probes are not served next to the webhook"
+            ));
+        }
+        if settings.sla_thresholds.as_deref().is_some_and(|t| sla::parse_thresholds(t).is_none()) {
+            return Err(ConfigError::Invalid(
+                "SLA_THRESHOLDS must be comma-separated durations, f.e. 15m,1h,4h"
+            ));
+        }
+        if settings.forum_routes.as_deref().is_some_and(|r| routing::parse_routes(r).is_none()) {
+            return Err(ConfigError::Invalid(
+                "FORUM_ROUTES must be comma-separated routes, f.e. language:ru=-100123,source:ads=-100456,category:billing=-100789"
+            ));
+        }
+        if settings.overflow_forums.as_deref().is_some_and(|f| routing::parse_forums(f).is_none()) {
+            return Err(ConfigError::Invalid(
+                "OVERFLOW_FORUMS must be comma-separated forum ids, f.e. -100123,-100456"
+            ));
+        }
+        if settings.max_open_topics.is_some_and(|max| max < 1) {
+            return Err(ConfigError::Invalid("MAX_OPEN_TOPICS must be a positive number"));
+        }
+        if settings.rate_limit_burst.is_some_and(|burst| burst < 1) || settings.rate_limit_violations < 1 {
+            return Err(ConfigError::Invalid("RATE_LIMIT_BURST and RATE_LIMIT_VIOLATIONS must be positive numbers"));
+        }
+        if parse_duration(&settings.rate_limit_refill).is_none() || parse_duration(&settings.rate_limit_mute).is_none() {
+            return Err(ConfigError::Invalid("RATE_LIMIT_REFILL and RATE_LIMIT_MUTE must be durations, f.e. 3s or 1h"));
+        }
         Ok(settings)
     }
 }
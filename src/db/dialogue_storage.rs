@@ -0,0 +1,72 @@
+use crate::db::redis::RedisAPI;
+use crate::errors;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use teloxide::dispatching::dialogue::Storage;
+use teloxide::types::ChatId;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = errors::Result<T>> + Send>>;
+
+/// Dialogue storage for teloxide that keeps the states in Redis as JSON,
+/// so they are shared between instances and survive restarts.
+#[derive(Clone)]
+pub struct DialogueStorage {
+    redis_cache: RedisAPI,
+}
+
+impl DialogueStorage {
+    pub fn new(redis_cache: RedisAPI) -> Arc<Self> {
+        Arc::new(Self { redis_cache })
+    }
+}
+
+impl<D> Storage<D> for DialogueStorage
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Error = errors::Error;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<()> {
+        Box::pin(async move {
+            self.redis_cache.clone().delete_dialogue(chat_id.0).await
+        })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, dialogue: D) -> BoxFuture<()> {
+        Box::pin(async move {
+            let dialogue = serde_json::to_string(&dialogue)?;
+            self.redis_cache.clone().save_dialogue(chat_id.0, &dialogue).await
+        })
+    }
+
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> BoxFuture<Option<D>> {
+        Box::pin(async move {
+            let Some(dialogue) = self.redis_cache.clone().get_dialogue(chat_id.0).await? else {
+                return Ok(None);
+            };
+            Ok(Some(serde_json::from_str(&dialogue)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::redis::tests::get_test_redis;
+    use teloxide::dispatching::dialogue::Dialogue;
+
+    #[tokio::test]
+    async fn test_dialogue_storage() {
+        let storage = DialogueStorage::new(get_test_redis().await);
+        let dialogue: Dialogue<Vec<String>, DialogueStorage> = Dialogue::new(storage, ChatId(90));
+
+        assert_eq!(dialogue.get().await.expect("Failed to get dialogue"), None);
+        dialogue.update(vec!["billing".to_string()]).await.expect("Failed to update dialogue");
+        assert_eq!(dialogue.get().await.expect("Failed to get dialogue"), Some(vec!["billing".to_string()]));
+        dialogue.exit().await.expect("Failed to exit dialogue");
+        assert_eq!(dialogue.get().await.expect("Failed to get dialogue"), None);
+    }
+}
@@ -1,7 +1,15 @@
 mod models;
 mod sqlite;
 mod redis;
+mod sync_buffer;
+mod stats_buffer;
+mod dialogue_storage;
 
 pub use models::*;
 pub use sqlite::Database;
 pub use redis::RedisAPI;
+pub use dialogue_storage::DialogueStorage;
+#[cfg(test)]
+pub use redis::tests::get_test_redis;
+#[cfg(test)]
+pub use sqlite::tests::profile;
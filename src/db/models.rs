@@ -1,17 +1,23 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use teloxide::types::{ChatId, MessageId};
 
-type MappingTuple = (i64, i64, i32, i32);
+type MappingTuple = (i64, i64, i32, i32, i64);
 
 /// Represents a link between chat rooms: a user in private messages and a forum topic.
 /// The values of the `sender_chat` i `recipient_chat` fields can be swapped.
-/// When receiving the structure, `sender_chat` is the initiator.
+/// When receiving the structure, `sender_chat` is the initiator,
+/// `from_topic` is set when the initiator is the topic.
 /// The `last_private`, `last_topic` fields store the id of the last message in a private and a topic respectively.
+/// The `forum_id` field is the forum the topic lives in.
 #[derive(Clone, Copy, Debug)]
 pub struct MappingChat {
     pub sender_chat: ChatId,
     pub recipient_chat: ChatId,
     pub last_private: MessageId,
     pub last_topic: MessageId,
+    pub forum_id: ChatId,
+    pub from_topic: bool,
 }
 
 impl MappingChat {
@@ -20,12 +26,25 @@ impl MappingChat {
         forum_topic: ChatId, 
         last_private: MessageId, 
         last_topic: MessageId,
+        forum_id: ChatId,
     ) -> Self {
         Self {
             sender_chat: private_chat,
             recipient_chat: forum_topic,
             last_private,
             last_topic,
+            forum_id,
+            from_topic: false,
+        }
+    }
+
+    /// Returns the same mapping seen from the other chat.
+    pub fn reversed(self) -> Self {
+        Self {
+            sender_chat: self.recipient_chat,
+            recipient_chat: self.sender_chat,
+            from_topic: !self.from_topic,
+            ..self
         }
     }
 
@@ -34,29 +53,254 @@ impl MappingChat {
         self.last_topic = last_topic;
     }
 
+    pub fn private_chat(&self) -> ChatId {
+        if self.from_topic { self.recipient_chat } else { self.sender_chat }
+    }
+
+    pub fn topic_chat(&self) -> ChatId {
+        if self.from_topic { self.sender_chat } else { self.recipient_chat }
+    }
+
+    /// Thread ids repeat in different forums, so the mapping is identified by the private chat.
     pub fn unique_id(&self) -> i64 {
-        self.sender_chat.0.min(self.recipient_chat.0)
+        self.private_chat().0
     }
 }
 
+/// The tuple is `(private_chat, topic_chat, last_private, last_topic, forum_id)`.
 impl From<MappingTuple> for MappingChat {
     fn from(tuple: MappingTuple) -> Self {
-        Self {
-            sender_chat: ChatId(tuple.0),
-            recipient_chat: ChatId(tuple.1),
-            last_private: MessageId(tuple.2),
-            last_topic: MessageId(tuple.3),
-        }
+        Self::new(
+            ChatId(tuple.0),
+            ChatId(tuple.1),
+            MessageId(tuple.2),
+            MessageId(tuple.3),
+            ChatId(tuple.4),
+        )
     }
 }
 
 impl From<MappingChat> for MappingTuple {
     fn from(mapping: MappingChat) -> Self {
         (
-            mapping.sender_chat.0, 
-            mapping.recipient_chat.0, 
+            mapping.private_chat().0, 
+            mapping.topic_chat().0, 
             mapping.last_private.0, 
-            mapping.last_topic.0
+            mapping.last_topic.0,
+            mapping.forum_id.0,
         )
     }
 }
+
+/// A reminder that an admin left in a topic.
+/// When `reopen` is set, the topic was snoozed and is reopened with the reminder.
+/// `due_at` is a unix timestamp in seconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reminder {
+    pub id: i64,
+    pub forum_id: i64,
+    pub thread_id: i32,
+    pub admin_id: u64,
+    pub admin_name: String,
+    pub text: String,
+    pub due_at: i64,
+    pub reopen: bool,
+}
+
+/// The first user message in a topic that no admin has answered yet.
+/// `since` is a unix timestamp in seconds, `escalation` is the number of alerts already sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Waiting {
+    pub private_chat: i64,
+    pub forum_id: i64,
+    pub thread_id: i32,
+    pub since: i64,
+    pub escalation: u32,
+}
+
+/// Support activity counted per day for the digest and `/stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    NewUser,
+    MessageIn,
+    MessageOut,
+    TopicClosed,
+    Ban,
+}
+
+impl Event {
+    pub const ALL: [Event; 5] = [Event::NewUser, Event::MessageIn, Event::MessageOut, Event::TopicClosed, Event::Ban];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::NewUser => "new_user",
+            Event::MessageIn => "message_in",
+            Event::MessageOut => "message_out",
+            Event::TopicClosed => "topic_closed",
+            Event::Ban => "ban",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.as_str() == name)
+    }
+}
+
+/// Activity over a period of time.
+/// `response_times` are the first-response times in seconds, in ascending order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub events: HashMap<Event, i64>,
+    pub response_times: Vec<i64>,
+}
+
+impl Stats {
+    pub fn count(&self, event: Event) -> i64 {
+        self.events.get(&event).copied().unwrap_or(0)
+    }
+
+    /// Returns the nearest-rank percentile of the response times, `p` is in `0..=100`.
+    pub fn response_percentile(&self, p: u32) -> Option<i64> {
+        if self.response_times.is_empty() {
+            return None;
+        }
+        let rank = (p as usize * self.response_times.len()).div_ceil(100).max(1);
+        Some(self.response_times[rank - 1])
+    }
+}
+
+/// Current totals, independent of any period.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub active_mappings: i64,
+    pub banned: i64,
+}
+
+/// What the pinned card in the topic shows about the user.
+/// `first_seen` and `last_seen` are unix timestamps in seconds,
+/// `card_message_id` is the pinned card, if it is known,
+/// `source` is the `/start` payload the user came with,
+/// `intake` is the form the user filled before the topic was created,
+/// `owner` is the admin the topic is assigned to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserProfile {
+    pub chat_id: i64,
+    pub forum_id: i64,
+    pub thread_id: i32,
+    pub full_name: String,
+    pub username: Option<String>,
+    pub language_code: Option<String>,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub messages_in: i64,
+    pub messages_out: i64,
+    pub card_message_id: Option<i32>,
+    pub source: Option<String>,
+    pub intake: Option<Intake>,
+    pub owner: Option<Owner>,
+}
+
+impl UserProfile {
+    /// Returns `true` if the fields that the user can change are the same.
+    pub fn same_fields(&self, other: &UserProfile) -> bool {
+        self.forum_id == other.forum_id
+            && self.thread_id == other.thread_id
+            && self.full_name == other.full_name
+            && self.username == other.username
+            && self.language_code == other.language_code
+    }
+
+    /// Identifies the changeable fields for the cache.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "{}\u{1f}{}\u{1f}{}\u{1f}{}\u{1f}{}",
+            self.forum_id,
+            self.thread_id,
+            self.full_name,
+            self.username.as_deref().unwrap_or_default(),
+            self.language_code.as_deref().unwrap_or_default(),
+        )
+    }
+}
+
+/// The admin a topic is assigned to with `/assign` or `/take`.
+/// `id` is unknown when the admin was assigned by the username, `name` is the username or the full name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Owner {
+    pub id: Option<i64>,
+    pub name: String,
+}
+
+/// Administrative actions recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    Ban,
+    DropTopic,
+    Assign,
+    Role,
+    Alias,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 5] = [
+        AuditAction::Ban, AuditAction::DropTopic, AuditAction::Assign, AuditAction::Role, AuditAction::Alias,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Ban => "ban",
+            AuditAction::DropTopic => "drop_topic",
+            AuditAction::Assign => "assign",
+            AuditAction::Role => "role",
+            AuditAction::Alias => "alias",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.as_str() == name)
+    }
+}
+
+/// An entry of the audit log.
+/// `actor_id` is the admin, `target` is the user the action is about,
+/// `args` are the arguments of the command, `ts` is a unix timestamp in seconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub actor_id: i64,
+    pub actor_name: String,
+    pub action: AuditAction,
+    pub target: Option<i64>,
+    pub forum_id: i64,
+    pub thread_id: Option<i32>,
+    pub args: Option<String>,
+    pub ts: i64,
+}
+
+/// A relayed text indexed for `/search`.
+/// `message_id` is the copy in the forum topic, `sent_at` is a unix timestamp in seconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryMessage {
+    pub private_chat: i64,
+    pub forum_id: i64,
+    pub thread_id: i32,
+    pub message_id: i32,
+    pub outgoing: bool,
+    pub sent_at: i64,
+    pub text: String,
+}
+
+/// Filters of `/search`, the dates are unix timestamps in seconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub private_chat: Option<i64>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// Answers of the intake form: the chosen category and the answered questions.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Intake {
+    pub category: String,
+    pub answers: Vec<(String, String)>,
+}
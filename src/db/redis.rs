@@ -2,66 +2,217 @@ use crate::db::models::MappingChat;
 use crate::errors;
 use redis::aio::MultiplexedConnection;
 use redis::AsyncCommands;
+use std::sync::LazyLock;
 use url::Url;
 
+/// Hash of mapping syncs that are not written to SQLite yet.
+/// Unlike the cache it has no TTL, so it is replayed after a crash.
+const OUTBOX_KEY: &str = "sync_outbox";
+
+/// Unfinished dialogues are forgotten after a day.
+const DIALOGUE_TTL: u64 = 24 * 60 * 60;
+
+/// Roles that follow from the status in the forum are asked from Telegram again after 10 minutes.
+const ROLE_TTL: u64 = 10 * 60;
+
+/// Removes an outbox entry only if it was not overwritten by a newer sync.
+static ACK_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| redis::Script::new(r#"
+    if redis.call("HGET", KEYS[1], ARGV[1]) == ARGV[2] then
+        return redis.call("HDEL", KEYS[1], ARGV[1])
+    end
+    return 0
+"#));
+
+/// Takes a token from a rate limit bucket, so that the instances of the bot share it.
+/// Returns the verdict: 0 allowed, 1 warned, 2 dropped, 3 muted, 4 still muted.
+/// Mirrors `Bucket::take` of the in-memory limiter, the bucket expires once it is full again.
+static TAKE_TOKEN_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| redis::Script::new(r#"
+    local now, burst, refill = tonumber(ARGV[1]), tonumber(ARGV[2]), tonumber(ARGV[3])
+    local max_violations, mute = tonumber(ARGV[4]), tonumber(ARGV[5])
+    local bucket = redis.call("HMGET", KEYS[1], "tokens", "updated_at", "violations", "muted_until")
+    local tokens = tonumber(bucket[1]) or burst
+    local updated_at = tonumber(bucket[2]) or now
+    local violations = tonumber(bucket[3]) or 0
+    local muted_until = tonumber(bucket[4]) or 0
+    if now < muted_until then
+        return 4
+    end
+    tokens = math.min(tokens + math.max(now - updated_at, 0) / refill, burst)
+    local verdict
+    if tokens >= 1 then
+        if tokens >= burst then
+            violations = 0
+        end
+        tokens = tokens - 1
+        verdict = 0
+    else
+        violations = violations + 1
+        if violations >= max_violations then
+            tokens, violations, muted_until, verdict = burst, 0, now + mute, 3
+        elseif violations == 1 then
+            verdict = 1
+        else
+            verdict = 2
+        end
+    end
+    redis.call("HSET", KEYS[1], "tokens", tostring(tokens), "updated_at", now,
+        "violations", violations, "muted_until", muted_until)
+    redis.call("PEXPIRE", KEYS[1], math.max(math.ceil((burst - tokens) * refill), muted_until - now, 1))
+    return verdict
+"#));
+
 #[derive(Clone)]
 pub struct RedisAPI {
     conn: MultiplexedConnection,
     key_ttl: i64,
+    outbox_key: String,
 }
 
 impl RedisAPI {
     pub async fn new(redis_url: &Url, key_ttl: i64) -> errors::Result<Self> {
         let client = redis::Client::open(redis_url.as_str())?;
         let conn: MultiplexedConnection = client.get_multiplexed_async_connection().await?;
-        Ok(Self { conn, key_ttl })
+        Ok(Self { conn, key_ttl, outbox_key: OUTBOX_KEY.to_string() })
+    }
+
+    fn mapping_key(&self, private_chat: i64) -> String {
+        format!("mapping:{}", private_chat)
     }
 
-    fn mapping_key(&self, chat_id: i64) -> String {
-        format!("mapping:{}", chat_id)
+    /// Thread ids repeat in different forums, so the topic key contains the forum.
+    fn topic_mapping_key(&self, forum_id: i64, topic_chat: i64) -> String {
+        format!("mapping:{}:{}", forum_id, topic_chat)
     }
 
-    fn mapping_value(&self, relevant_chat: i64, last_private: i32, last_topic: i32) -> String {
-        format!("{}:{}:{}", relevant_chat, last_private, last_topic)
+    fn mapping_value(&self, relevant_chat: i64, last_private: i32, last_topic: i32, forum_id: i64) -> String {
+        format!("{}:{}:{}:{}", relevant_chat, last_private, last_topic, forum_id)
+    }
+
+    /// Parses the value of a mapping key into the other chat, the last messages and the forum.
+    fn parse_mapping_value(&self, value: &str) -> errors::Result<(i64, i32, i32, i64)> {
+        let mut parts = value.split(':');
+        let relevant_chat = parts.next().expect("infallible").parse::<i64>()?;
+        let last_private = parts.next().unwrap_or_default().parse::<i32>()?;
+        let last_topic = parts.next().unwrap_or_default().parse::<i32>()?;
+        // Values cached before multiple forums were supported fail here and are read from SQLite
+        let forum_id = parts.next().unwrap_or_default().parse::<i64>()?;
+        Ok((relevant_chat, last_private, last_topic, forum_id))
     }
     
+    fn outbox_value(&self, mapping: MappingChat) -> String {
+        let (private_chat, topic_chat, last_private, last_topic, forum_id) = mapping.into();
+        format!("{}:{}:{}:{}:{}", private_chat, topic_chat, last_private, last_topic, forum_id)
+    }
+
     fn banned_key(&self, private_chat: i64) -> String {
         format!("banned:{}", private_chat)
     }
+
+    fn profile_key(&self, private_chat: i64) -> String {
+        format!("profile:{}", private_chat)
+    }
+
+    fn dialogue_key(&self, private_chat: i64) -> String {
+        format!("dialogue:{}", private_chat)
+    }
+
+    fn waiting_key(&self, private_chat: i64) -> String {
+        format!("waiting:{}", private_chat)
+    }
+
+    fn role_key(&self, forum_id: i64, member_id: i64) -> String {
+        format!("role:{}:{}", forum_id, member_id)
+    }
+
+    fn reply_lock_key(&self, forum_id: i64, thread_id: i32) -> String {
+        format!("reply_lock:{}:{}", forum_id, thread_id)
+    }
+
+    fn bucket_key(&self, private_chat: i64) -> String {
+        format!("rate:{}", private_chat)
+    }
     
-    pub async fn save_mapping(&mut self, mapping: MappingChat) -> errors::Result<()> {
-        let (sender_chat, recipient_chat, last_private, last_topic) = mapping.into();
+    /// Builds an atomic pipeline that caches the mapping under the private chat and the topic.
+    fn mapping_pipeline(&self, mapping: MappingChat) -> redis::Pipeline {
+        let (private_chat, topic_chat) = (mapping.private_chat().0, mapping.topic_chat().0);
+        let (last_private, last_topic, forum_id) = (mapping.last_private.0, mapping.last_topic.0, mapping.forum_id.0);
         
-        let first_key = self.mapping_key(mapping.sender_chat.0);
-        let first_value = self.mapping_value(recipient_chat, last_private, last_topic);
+        let first_key = self.mapping_key(private_chat);
+        let first_value = self.mapping_value(topic_chat, last_private, last_topic, forum_id);
         
-        let second_key = self.mapping_key(mapping.recipient_chat.0);
-        let second_value = self.mapping_value(sender_chat, last_private, last_topic);
+        let second_key = self.topic_mapping_key(forum_id, topic_chat);
+        let second_value = self.mapping_value(private_chat, last_private, last_topic, forum_id);
         
-        redis::pipe()
-            .atomic()
+        let mut pipe = redis::pipe();
+        pipe.atomic()
             .set(&first_key, first_value)
             .set(&second_key, second_value)
             .expire(first_key, self.key_ttl)
-            .expire(second_key, self.key_ttl)
-            .query_async(&mut self.conn)
+            .expire(second_key, self.key_ttl);
+        pipe
+    }
+
+    pub async fn save_mapping(&mut self, mapping: MappingChat) -> errors::Result<()> {
+        self.mapping_pipeline(mapping)
+            .query_async::<()>(&mut self.conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Caches the mapping and records it in the outbox in one transaction.
+    pub async fn save_pending_sync(&mut self, mapping: MappingChat) -> errors::Result<()> {
+        self.mapping_pipeline(mapping)
+            .hset(&self.outbox_key, mapping.unique_id(), self.outbox_value(mapping))
+            .query_async::<()>(&mut self.conn)
             .await?;
 
         Ok(())
     }
 
-    pub async fn get_mapping(&mut self, chat_id: i64) -> errors::Result<Option<MappingChat>> {
-        let key = self.mapping_key(chat_id);
+    /// Returns all syncs from the outbox.
+    pub async fn pending_syncs(&mut self) -> errors::Result<Vec<MappingChat>> {
+        let entries: Vec<(i64, String)> = self.conn.hgetall(&self.outbox_key).await?;
+        let mut mappings = Vec::with_capacity(entries.len());
+        for (unique_id, value) in entries {
+            let parts = value.split(':').collect::<Vec<_>>();
+            // Syncs left by a version without multiple forums are keyed by the thread id,
+            // they are dropped: the last message ids are only used to thread replies
+            let [private_chat, topic_chat, last_private, last_topic, forum_id] = parts[..] else {
+                self.conn.hdel::<_, _, ()>(&self.outbox_key, unique_id).await?;
+                continue;
+            };
+            mappings.push(MappingChat::from((
+                private_chat.parse::<i64>()?, topic_chat.parse::<i64>()?,
+                last_private.parse::<i32>()?, last_topic.parse::<i32>()?,
+                forum_id.parse::<i64>()?,
+            )));
+        }
+        Ok(mappings)
+    }
+
+    /// Removes the sync from the outbox once it is written to SQLite.
+    /// A newer sync of the same mapping is kept.
+    pub async fn ack_sync(&mut self, mapping: MappingChat) -> errors::Result<()> {
+        ACK_SCRIPT
+            .key(&self.outbox_key)
+            .arg(mapping.unique_id())
+            .arg(self.outbox_value(mapping))
+            .invoke_async::<()>(&mut self.conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_mapping(&mut self, private_chat: i64) -> errors::Result<Option<MappingChat>> {
+        let key = self.mapping_key(private_chat);
         let mapping_data: Option<String> = self.conn.get(&key).await?;
 
         if let Some(mapping_data) = mapping_data {
-            let mut parts = mapping_data.split(':');
-            let recipient_chat = parts.next().expect("infallible").parse::<i64>()?;
-            let last_private = parts.next().expect("infallible").parse::<i32>()?;
-            let last_topic = parts.next().expect("infallible").parse::<i32>()?;
+            let (topic_chat, last_private, last_topic, forum_id) = self.parse_mapping_value(&mapping_data)?;
             let mapping = MappingChat::from((
-                chat_id, recipient_chat, 
+                private_chat, topic_chat, 
                 last_private, last_topic,
+                forum_id,
             ));
             Ok(Some(mapping))
         } else { 
@@ -69,20 +220,49 @@ impl RedisAPI {
         }
     }
 
-    pub async fn delete_mapping(&mut self, chat_id: i64) -> errors::Result<()> {
-        let first_key = self.mapping_key(chat_id);
+    pub async fn get_topic_mapping(&mut self, forum_id: i64, topic_chat: i64) -> errors::Result<Option<MappingChat>> {
+        let key = self.topic_mapping_key(forum_id, topic_chat);
+        let mapping_data: Option<String> = self.conn.get(&key).await?;
+
+        if let Some(mapping_data) = mapping_data {
+            let (private_chat, last_private, last_topic, _) = self.parse_mapping_value(&mapping_data)?;
+            let mapping = MappingChat::from((
+                private_chat, topic_chat,
+                last_private, last_topic,
+                forum_id,
+            )).reversed();
+            Ok(Some(mapping))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn delete_mapping(&mut self, private_chat: i64) -> errors::Result<()> {
+        let first_key = self.mapping_key(private_chat);
 
         let mapping_data: String = self.conn.get(&first_key).await?;
-        let mut parts = mapping_data.split(':');
-        let mapping_chat = parts.next().expect("infallible").parse::<i64>()?;
+        let (topic_chat, _, _, forum_id) = self.parse_mapping_value(&mapping_data)?;
+
+        let second_key = self.topic_mapping_key(forum_id, topic_chat);
+        self.delete_keys(first_key, second_key).await
+    }
+
+    pub async fn delete_topic_mapping(&mut self, forum_id: i64, topic_chat: i64) -> errors::Result<()> {
+        let first_key = self.topic_mapping_key(forum_id, topic_chat);
 
-        let second_key = self.mapping_key(mapping_chat);
+        let mapping_data: String = self.conn.get(&first_key).await?;
+        let (private_chat, _, _, _) = self.parse_mapping_value(&mapping_data)?;
+
+        let second_key = self.mapping_key(private_chat);
+        self.delete_keys(first_key, second_key).await
+    }
 
+    async fn delete_keys(&mut self, first_key: String, second_key: String) -> errors::Result<()> {
         redis::pipe()
             .atomic()
             .del(first_key)
             .del(second_key)
-            .query_async(&mut self.conn)
+            .query_async::<()>(&mut self.conn)
             .await?;
 
         Ok(())
@@ -90,8 +270,8 @@ impl RedisAPI {
 
     pub async fn ban_user(&mut self, private_chat: i64) -> errors::Result<()> {
         let key = self.banned_key(private_chat);
-        self.conn.set(&key, "").await?;
-        self.conn.expire(&key, self.key_ttl).await?;
+        self.conn.set::<_, _, ()>(&key, "").await?;
+        self.conn.expire::<_, ()>(&key, self.key_ttl).await?;
         Ok(())
     }
 
@@ -106,36 +286,157 @@ impl RedisAPI {
         }
         Ok(Some(banned))
     }
+
+    pub async fn get_profile_fingerprint(&mut self, private_chat: i64) -> errors::Result<Option<String>> {
+        Ok(self.conn.get(self.profile_key(private_chat)).await?)
+    }
+
+    pub async fn save_profile_fingerprint(&mut self, private_chat: i64, fingerprint: &str) -> errors::Result<()> {
+        self.conn.set_ex::<_, _, ()>(self.profile_key(private_chat), fingerprint, self.key_ttl as u64).await?;
+        Ok(())
+    }
+
+    pub async fn get_dialogue(&mut self, private_chat: i64) -> errors::Result<Option<String>> {
+        Ok(self.conn.get(self.dialogue_key(private_chat)).await?)
+    }
+
+    pub async fn save_dialogue(&mut self, private_chat: i64, dialogue: &str) -> errors::Result<()> {
+        self.conn.set_ex::<_, _, ()>(self.dialogue_key(private_chat), dialogue, DIALOGUE_TTL).await?;
+        Ok(())
+    }
+
+    pub async fn delete_dialogue(&mut self, private_chat: i64) -> errors::Result<()> {
+        self.conn.del::<_, ()>(self.dialogue_key(private_chat)).await?;
+        Ok(())
+    }
+
+    /// Marks the user as waiting for a reply.
+    /// The mark has no TTL, it lives until an admin answers.
+    /// Returns `true` if the user was not waiting yet.
+    pub async fn mark_waiting(&mut self, private_chat: i64) -> errors::Result<bool> {
+        let marked: Option<()> = redis::cmd("SET")
+            .arg(self.waiting_key(private_chat))
+            .arg("")
+            .arg("NX")
+            .query_async(&mut self.conn)
+            .await?;
+        Ok(marked.is_some())
+    }
+
+    /// Returns `true` if the user was waiting for a reply.
+    pub async fn clear_waiting(&mut self, private_chat: i64) -> errors::Result<bool> {
+        let removed: u64 = self.conn.del(self.waiting_key(private_chat)).await?;
+        Ok(removed > 0)
+    }
+
+    /// Locks the topic for the admin, or extends the lock if the admin already holds it.
+    /// Returns the name of another admin who holds the lock.
+    pub async fn lock_reply(
+        &mut self,
+        forum_id: i64,
+        thread_id: i32,
+        admin_id: u64,
+        admin_name: &str,
+        window: u64,
+    ) -> errors::Result<Option<String>> {
+        let key = self.reply_lock_key(forum_id, thread_id);
+        let value = format!("{admin_id}:{admin_name}");
+        let locked: Option<()> = redis::cmd("SET")
+            .arg(&key)
+            .arg(&value)
+            .arg("NX")
+            .arg("EX")
+            .arg(window)
+            .query_async(&mut self.conn)
+            .await?;
+        if locked.is_some() {
+            return Ok(None);
+        }
+        let holder: Option<String> = self.conn.get(&key).await?;
+        match holder.as_deref().and_then(|holder| holder.split_once(':')) {
+            Some((id, name)) if id != admin_id.to_string() => Ok(Some(name.to_string())),
+            // The lock is the admin's own, or it has just expired
+            _ => {
+                self.conn.set_ex::<_, _, ()>(key, value, window).await?;
+                Ok(None)
+            },
+        }
+    }
+
+    pub async fn get_role(&mut self, forum_id: i64, member_id: i64) -> errors::Result<Option<String>> {
+        Ok(self.conn.get(self.role_key(forum_id, member_id)).await?)
+    }
+
+    pub async fn save_role(&mut self, forum_id: i64, member_id: i64, role: &str) -> errors::Result<()> {
+        self.conn.set_ex::<_, _, ()>(self.role_key(forum_id, member_id), role, ROLE_TTL).await?;
+        Ok(())
+    }
+
+    /// Takes a token from the rate limit bucket of the private chat, see `TAKE_TOKEN_SCRIPT`.
+    /// The times are in milliseconds.
+    pub async fn take_token(
+        &mut self,
+        private_chat: i64,
+        now: i64,
+        burst: u32,
+        refill: u64,
+        violations: u32,
+        mute: u64,
+    ) -> errors::Result<u8> {
+        let verdict = TAKE_TOKEN_SCRIPT
+            .key(self.bucket_key(private_chat))
+            .arg(now)
+            .arg(burst)
+            .arg(refill)
+            .arg(violations)
+            .arg(mute)
+            .invoke_async(&mut self.conn)
+            .await?;
+        Ok(verdict)
+    }
+
+    pub async fn ping(&mut self) -> errors::Result<()> {
+        redis::cmd("PING").query_async::<()>(&mut self.conn).await?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     
-    // Also for SQLite tests
+    static FLUSHED: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+    static TEST_OUTBOXES: AtomicUsize = AtomicUsize::new(0);
+
+    // Also for SQLite tests. Redis is flushed once, since the tests run in parallel,
+    // and every test gets its own outbox.
     pub async fn get_test_redis() -> RedisAPI {
         let mut redis_api = RedisAPI::new(
             &Url::parse("redis://127.0.0.1/").unwrap(),
             60
         ).await.expect("Failed to connect to Redis");
-        let _: () = redis::cmd("FLUSHALL")
-            .query_async(&mut redis_api.conn)
-            .await
-            .expect("Failed to flush Redis");
+        FLUSHED.get_or_init(|| async {
+            let _: () = redis::cmd("FLUSHALL")
+                .query_async(&mut redis_api.conn.clone())
+                .await
+                .expect("Failed to flush Redis");
+        }).await;
+        redis_api.outbox_key = format!("{OUTBOX_KEY}:{}", TEST_OUTBOXES.fetch_add(1, Ordering::Relaxed));
         redis_api
     }
 
     #[tokio::test]
     async fn test_save_mapping() {
         let mut redis_api = get_test_redis().await;
-        let mapping = MappingChat::from((1, 2, 3, 4));
+        let mapping = MappingChat::from((1000, 2, 3, 4, -100));
         
         redis_api.save_mapping(mapping).await.expect("Failed to save mapping");
-        let first_mapping = redis_api.get_mapping(1)
+        let first_mapping = redis_api.get_mapping(1000)
             .await
             .expect("Failed to get mapping")
             .expect("Mapping not found");
-        let second_mapping = redis_api.get_mapping(2)
+        let second_mapping = redis_api.get_topic_mapping(-100, 2)
             .await
             .expect("Failed to get mapping")
             .expect("Mapping not found");
@@ -143,18 +444,28 @@ pub mod tests {
         assert_eq!(first_mapping.recipient_chat, second_mapping.sender_chat);
         assert_eq!(first_mapping.last_private, second_mapping.last_private);
         assert_eq!(first_mapping.last_topic, second_mapping.last_topic);
+        assert_eq!(first_mapping.forum_id, second_mapping.forum_id);
+        // The same thread in another forum is another topic
+        let other_forum = redis_api.get_topic_mapping(-200, 2).await;
+        assert!(other_forum.is_ok_and(|m| m.is_none()));
     }
 
     #[tokio::test]
     async fn test_delete_mapping() {
         let mut redis_api = get_test_redis().await;
-        let mapping = MappingChat::from((5, 6, 7, 8));
+        let mapping = MappingChat::from((5000, 6, 7, 8, -100));
         
-        let fetched_mapping = redis_api.get_mapping(5).await;
+        let fetched_mapping = redis_api.get_mapping(5000).await;
         assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
         redis_api.save_mapping(mapping).await.expect("Failed to save mapping");
-        redis_api.delete_mapping(5).await.expect("Failed to delete mapping");
-        let fetched_mapping = redis_api.get_mapping(5).await;
+        redis_api.delete_mapping(5000).await.expect("Failed to delete mapping");
+        let fetched_mapping = redis_api.get_mapping(5000).await;
+        assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
+        let fetched_mapping = redis_api.get_topic_mapping(-100, 6).await;
+        assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
+        redis_api.save_mapping(mapping).await.expect("Failed to save mapping");
+        redis_api.delete_topic_mapping(-100, 6).await.expect("Failed to delete mapping");
+        let fetched_mapping = redis_api.get_mapping(5000).await;
         assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
     }
 
@@ -168,4 +479,45 @@ pub mod tests {
         let banned = redis_api.check_ban(13).await.expect("Failed to check ban");
         assert!(banned.is_some());
     }
+
+    #[tokio::test]
+    async fn test_outbox() {
+        let mut redis_api = get_test_redis().await;
+        let mapping = MappingChat::from((14000, 15, 16, 17, -100));
+        let newer_mapping = MappingChat::from((14000, 15, 18, 19, -100)).reversed();
+
+        redis_api.save_pending_sync(mapping).await.expect("Failed to save sync");
+        redis_api.save_pending_sync(newer_mapping).await.expect("Failed to save sync");
+        let pending = redis_api.pending_syncs().await.expect("Failed to get syncs");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].last_private, newer_mapping.last_private);
+        assert_eq!(pending[0].forum_id, newer_mapping.forum_id);
+        // An outdated sync does not remove the newer one
+        redis_api.ack_sync(mapping).await.expect("Failed to ack sync");
+        assert_eq!(redis_api.pending_syncs().await.expect("Failed to get syncs").len(), 1);
+        redis_api.ack_sync(newer_mapping).await.expect("Failed to ack sync");
+        assert!(redis_api.pending_syncs().await.expect("Failed to get syncs").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_waiting() {
+        let mut redis_api = get_test_redis().await;
+
+        assert!(redis_api.mark_waiting(20).await.expect("Failed to mark waiting"));
+        assert!(!redis_api.mark_waiting(20).await.expect("Failed to mark waiting"));
+        assert!(redis_api.clear_waiting(20).await.expect("Failed to clear waiting"));
+        assert!(!redis_api.clear_waiting(20).await.expect("Failed to clear waiting"));
+    }
+
+    #[tokio::test]
+    async fn test_reply_lock() {
+        let mut redis_api = get_test_redis().await;
+
+        assert_eq!(redis_api.lock_reply(-100, 21, 1, "Alice", 60).await.expect("Failed to lock"), None);
+        assert_eq!(redis_api.lock_reply(-100, 21, 1, "Alice", 60).await.expect("Failed to lock"), None);
+        let holder = redis_api.lock_reply(-100, 21, 2, "Bob", 60).await.expect("Failed to lock");
+        assert_eq!(holder.as_deref(), Some("Alice"));
+        // Topics are locked separately
+        assert_eq!(redis_api.lock_reply(-200, 21, 2, "Bob", 60).await.expect("Failed to lock"), None);
+    }
 }
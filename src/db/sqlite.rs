@@ -1,8 +1,12 @@
-use crate::db::models::MappingChat;
+use crate::db::models::{AuditAction, AuditEntry, Event, HistoryMessage, Intake, MappingChat, Owner, Reminder, SearchQuery, Stats, Totals, UserProfile, Waiting};
 use crate::db::redis::RedisAPI;
+use crate::db::stats_buffer::{day_of, StatsBuffer};
+use crate::db::sync_buffer::SyncBuffer;
 use crate::errors;
+use crate::roles::Role;
+use crate::utils::unix_now;
 use sqlx::migrate::MigrateDatabase;
-use sqlx::{Executor, Row, Sqlite, SqlitePool};
+use sqlx::{Executor, Sqlite, SqlitePool};
 use crate::scheduler::Scheduler;
 
 async fn create_sqlite_pool(path: &str) -> errors::Result<SqlitePool> {
@@ -17,10 +21,29 @@ async fn create_sqlite_pool(path: &str) -> errors::Result<SqlitePool> {
                private_chat INTEGER NOT NULL PRIMARY KEY,
                topic_chat INTEGER NOT NULL,
                last_private INTEGER NOT NULL,
-               last_topic INTEGER NOT NULL
+               last_topic INTEGER NOT NULL,
+               forum_id INTEGER,
+               owner_id INTEGER,
+               owner_name TEXT
            );
            "#
     ).await?;
+    // Mappings created before multiple forums were supported have no forum,
+    // `Database::assign_default_forum` assigns them to FORUM_ID
+    for (column, kind) in [("forum_id", "INTEGER"), ("owner_id", "INTEGER"), ("owner_name", "TEXT")] {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('mapping') WHERE name = ?;"
+        )
+            .bind(column)
+            .fetch_one(&pool)
+            .await?;
+        if !exists {
+            pool.execute(format!("ALTER TABLE mapping ADD COLUMN {column} {kind};").as_str()).await?;
+        }
+    }
+    pool.execute(
+        "CREATE INDEX IF NOT EXISTS mapping_topic ON mapping (forum_id, topic_chat);"
+    ).await?;
     pool.execute(
         r#"
            CREATE TABLE IF NOT EXISTS banned (
@@ -28,34 +51,198 @@ async fn create_sqlite_pool(path: &str) -> errors::Result<SqlitePool> {
            );
            "#
     ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS reminders (
+               id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+               forum_id INTEGER NOT NULL,
+               thread_id INTEGER NOT NULL,
+               admin_id INTEGER NOT NULL,
+               admin_name TEXT NOT NULL,
+               text TEXT NOT NULL,
+               due_at INTEGER NOT NULL,
+               reopen INTEGER NOT NULL
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS waiting (
+               private_chat INTEGER NOT NULL PRIMARY KEY,
+               forum_id INTEGER NOT NULL,
+               thread_id INTEGER NOT NULL,
+               since INTEGER NOT NULL,
+               escalation INTEGER NOT NULL DEFAULT 0
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS daily_stats (
+               day INTEGER NOT NULL,
+               event TEXT NOT NULL,
+               count INTEGER NOT NULL,
+               PRIMARY KEY (day, event)
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS response_times (
+               answered_at INTEGER NOT NULL,
+               seconds INTEGER NOT NULL
+           );
+           CREATE INDEX IF NOT EXISTS response_times_answered_at ON response_times (answered_at);
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS users (
+               chat_id INTEGER NOT NULL PRIMARY KEY,
+               forum_id INTEGER NOT NULL,
+               thread_id INTEGER NOT NULL,
+               full_name TEXT NOT NULL,
+               username TEXT,
+               language_code TEXT,
+               first_seen INTEGER NOT NULL,
+               last_seen INTEGER NOT NULL,
+               messages_in INTEGER NOT NULL DEFAULT 0,
+               messages_out INTEGER NOT NULL DEFAULT 0,
+               card_message_id INTEGER,
+               intake TEXT
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE VIRTUAL TABLE IF NOT EXISTS history USING fts5 (
+               text,
+               private_chat UNINDEXED,
+               forum_id UNINDEXED,
+               thread_id UNINDEXED,
+               message_id UNINDEXED,
+               outgoing UNINDEXED,
+               sent_at UNINDEXED
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS sources (
+               chat_id INTEGER NOT NULL PRIMARY KEY,
+               source TEXT NOT NULL,
+               started_at INTEGER NOT NULL
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS ban_history (
+               chat_id INTEGER NOT NULL,
+               banned_at INTEGER NOT NULL
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS aliases (
+               admin_id INTEGER NOT NULL PRIMARY KEY,
+               alias TEXT NOT NULL
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS roles (
+               member TEXT NOT NULL PRIMARY KEY,
+               role TEXT NOT NULL
+           );
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS members (
+               member_id INTEGER NOT NULL PRIMARY KEY,
+               username TEXT
+           );
+           CREATE UNIQUE INDEX IF NOT EXISTS members_username ON members (username);
+           "#
+    ).await?;
+    pool.execute(
+        r#"
+           CREATE TABLE IF NOT EXISTS audit_log (
+               id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+               actor_id INTEGER NOT NULL,
+               actor_name TEXT NOT NULL,
+               action TEXT NOT NULL,
+               target INTEGER,
+               forum_id INTEGER NOT NULL,
+               thread_id INTEGER,
+               args TEXT,
+               ts INTEGER NOT NULL
+           );
+           CREATE INDEX IF NOT EXISTS audit_log_target ON audit_log (target);
+           "#
+    ).await?;
     Ok(pool)
 }
 
+type UserRow = (
+    i64, i64, i32, String, Option<String>, Option<String>,
+    i64, i64, i64, i64, Option<i32>, Option<String>, Option<sqlx::types::Json<Intake>>,
+    Option<i64>, Option<String>,
+);
+
+type AuditRow = (i64, String, String, Option<i64>, i64, Option<i32>, Option<String>, i64);
+
+fn user_from_row(row: UserRow) -> UserProfile {
+    UserProfile {
+        chat_id: row.0,
+        forum_id: row.1,
+        thread_id: row.2,
+        full_name: row.3,
+        username: row.4,
+        language_code: row.5,
+        first_seen: row.6,
+        last_seen: row.7,
+        messages_in: row.8,
+        messages_out: row.9,
+        card_message_id: row.10,
+        source: row.11,
+        intake: row.12.map(|intake| intake.0),
+        owner: row.14.map(|name| Owner { id: row.13, name }),
+    }
+}
+
 #[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
     redis_cache: RedisAPI,
+    sync_buffer: SyncBuffer,
+    stats_buffer: StatsBuffer,
 }
 
 impl Database {
-    pub async fn new(sqlite_path: &str, redis_cache: RedisAPI) -> errors::Result<Self> {
+    pub async fn new(sqlite_path: &str, redis_cache: RedisAPI, sync_batch_size: usize) -> errors::Result<Self> {
         let pool = create_sqlite_pool(sqlite_path).await?;
-        Ok(Self { pool, redis_cache })
+        let sync_buffer = SyncBuffer::new(pool.clone(), redis_cache.clone(), sync_batch_size);
+        let stats_buffer = StatsBuffer::new(pool.clone());
+        Ok(Self { pool, redis_cache, sync_buffer, stats_buffer })
     }
 
     pub async fn save_mapping(&mut self, mapping: MappingChat) -> errors::Result<()> {
         self.redis_cache.save_mapping(mapping).await?;
-        let (sender_chat, recipient_chat, last_private, last_topic) = mapping.into();
         sqlx::query(
             r#"
-               INSERT INTO mapping (private_chat, topic_chat, last_private, last_topic)
-               VALUES (?, ?, ?, ?)
+               INSERT INTO mapping (private_chat, topic_chat, last_private, last_topic, forum_id)
+               VALUES (?, ?, ?, ?, ?)
                "#
         )
-            .bind(sender_chat)
-            .bind(recipient_chat)
-            .bind(last_private)
-            .bind(last_topic)
+            .bind(mapping.private_chat().0)
+            .bind(mapping.topic_chat().0)
+            .bind(mapping.last_private.0)
+            .bind(mapping.last_topic.0)
+            .bind(mapping.forum_id.0)
             .execute(&self.pool)
             .await?;
 
@@ -63,64 +250,60 @@ impl Database {
     }
 
     pub async fn sync_mapping(&mut self, mapping: MappingChat, scheduler: Scheduler) -> errors::Result<()> {
-        self.redis_cache.save_mapping(mapping).await?;
-        // Schedule the task to run in the background,
+        // The outbox keeps the sync until it reaches SQLite, even if the bot crashes
+        self.redis_cache.save_pending_sync(mapping).await?;
+        // Dirty mappings are written in batches in the background,
         // there will be no database query spam!
-        let pool = self.pool.clone();
-        let task_id = mapping.unique_id() as u64;
-        let (sender_chat, _, last_private, last_topic) = mapping.into();
-        scheduler.add_task(task_id, move || async move {
-            let _ = sqlx::query(
-                r#"
-                   UPDATE mapping
-                   SET last_private = ?, last_topic = ?
-                   WHERE private_chat = ? OR topic_chat = ?;
-                   "#
-            )
-                .bind(last_private)
-                .bind(last_topic)
-                .bind(sender_chat)
-                .bind(sender_chat)
-                .execute(&pool)
-                .await;
-            tracing::info!("Successfully synchronized mapping: {task_id}");
-        });
+        self.sync_buffer.push(mapping, &scheduler).await
+    }
+
+    /// Drops the scheduled sync of the mapping, f.e. when the mapping is deleted.
+    pub fn cancel_sync(&self, mapping: MappingChat) -> bool {
+        self.sync_buffer.cancel(mapping)
+    }
+
+    /// Writes the syncs left in the outbox by the previous run to SQLite.
+    /// Returns the number of replayed syncs.
+    pub async fn replay_outbox(&mut self) -> errors::Result<usize> {
+        let pending = self.redis_cache.pending_syncs().await?;
+        self.sync_buffer.extend(pending);
+        self.sync_buffer.flush().await
+    }
+
+    /// Assigns the mappings created before multiple forums were supported to the default forum.
+    pub async fn assign_default_forum(&self, forum_id: i64) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               UPDATE mapping
+               SET forum_id = ?
+               WHERE forum_id IS NULL;
+               "#
+        )
+            .bind(forum_id)
+            .execute(&self.pool)
+            .await?;
 
         Ok(())
     }
 
-    pub async fn get_mapping(&mut self, chat_id: i64) -> errors::Result<Option<MappingChat>> {
-        if let Ok(Some(mapping)) = self.redis_cache.get_mapping(chat_id).await {
+    /// Returns the mapping of the private chat, `sender_chat` is the private chat.
+    pub async fn get_mapping(&mut self, private_chat: i64) -> errors::Result<Option<MappingChat>> {
+        if let Ok(Some(mapping)) = self.redis_cache.get_mapping(private_chat).await {
             return Ok(Some(mapping));
         }
-        let mapping = sqlx::query(
-            r#"
-           SELECT
-               CASE
-                   WHEN private_chat = ? THEN topic_chat
-                   ELSE private_chat
-               END AS recipient_chat,
-               last_private,
-               last_topic
-           FROM mapping
-           WHERE private_chat = ? OR topic_chat = ?;
-        "#
+        let mapping = sqlx::query_as::<_, (i64, i32, i32, i64)>(
+            r#"
+               SELECT topic_chat, last_private, last_topic, forum_id
+               FROM mapping
+               WHERE private_chat = ?;
+               "#
         )
-            .bind(chat_id)
-            .bind(chat_id)
-            .bind(chat_id)
+            .bind(private_chat)
             .fetch_optional(&self.pool)
-            .await
-            .map(|row| {
-                row.map(|row|
-                    MappingChat::from((
-                        chat_id,
-                        row.get(0),
-                        row.get(1),
-                        row.get(2),
-                    ))
-                )
-            })?;
+            .await?
+            .map(|(topic_chat, last_private, last_topic, forum_id)| {
+                MappingChat::from((private_chat, topic_chat, last_private, last_topic, forum_id))
+            });
 
         if let Some(mapping) = mapping {
             self.redis_cache.save_mapping(mapping).await?;
@@ -128,17 +311,45 @@ impl Database {
         Ok(mapping)
     }
 
-    pub async fn drop_mapping(&mut self, topic_chat: i64) -> errors::Result<()> {
+    /// Returns the mapping of the topic, `sender_chat` is the topic.
+    /// Thread ids repeat in different forums, so the topic is looked up with its forum.
+    pub async fn get_topic_mapping(&mut self, forum_id: i64, topic_chat: i64) -> errors::Result<Option<MappingChat>> {
+        if let Ok(Some(mapping)) = self.redis_cache.get_topic_mapping(forum_id, topic_chat).await {
+            return Ok(Some(mapping));
+        }
+        let mapping = sqlx::query_as::<_, (i64, i32, i32)>(
+            r#"
+               SELECT private_chat, last_private, last_topic
+               FROM mapping
+               WHERE forum_id = ? AND topic_chat = ?;
+               "#
+        )
+            .bind(forum_id)
+            .bind(topic_chat)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|(private_chat, last_private, last_topic)| {
+                MappingChat::from((private_chat, topic_chat, last_private, last_topic, forum_id)).reversed()
+            });
+
+        if let Some(mapping) = mapping {
+            self.redis_cache.save_mapping(mapping).await?;
+        }
+        Ok(mapping)
+    }
+
+    pub async fn drop_mapping(&mut self, forum_id: i64, topic_chat: i64) -> errors::Result<()> {
         sqlx::query(
             r#"
                DELETE FROM mapping
-               WHERE topic_chat = ?;
+               WHERE forum_id = ? AND topic_chat = ?;
                "#
         )
+            .bind(forum_id)
             .bind(topic_chat)
             .execute(&self.pool)
             .await?;
-        self.redis_cache.delete_mapping(topic_chat).await?;
+        self.redis_cache.delete_topic_mapping(forum_id, topic_chat).await?;
         
         Ok(())
     }
@@ -153,6 +364,16 @@ impl Database {
             .bind(private_chat)
             .execute(&self.pool)
             .await?;
+        sqlx::query(
+            r#"
+               INSERT INTO ban_history (chat_id, banned_at)
+               VALUES (?, ?);
+               "#
+        )
+            .bind(private_chat)
+            .bind(unix_now())
+            .execute(&self.pool)
+            .await?;
         sqlx::query(
             r#"
                DELETE FROM mapping
@@ -195,64 +416,1309 @@ impl Database {
         }
         Ok(banned)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::db::redis::tests::get_test_redis;
-    
-    async fn setup_sqlite() -> Database {
-        let redis_cache = get_test_redis().await;
-        let pool = create_sqlite_pool(":memory:")
-            .await
-            .expect("Failed to create SQLite pool");
-        Database { pool, redis_cache }
+    /// Saves the reminder and returns its id.
+    pub async fn save_reminder(&self, reminder: &Reminder) -> errors::Result<i64> {
+        let id = sqlx::query(
+            r#"
+               INSERT INTO reminders (forum_id, thread_id, admin_id, admin_name, text, due_at, reopen)
+               VALUES (?, ?, ?, ?, ?, ?, ?);
+               "#
+        )
+            .bind(reminder.forum_id)
+            .bind(reminder.thread_id)
+            .bind(reminder.admin_id as i64)
+            .bind(&reminder.admin_name)
+            .bind(&reminder.text)
+            .bind(reminder.due_at)
+            .bind(reminder.reopen)
+            .execute(&self.pool)
+            .await?
+            .last_insert_rowid();
+
+        Ok(id)
     }
 
-    #[tokio::test]
-    async fn test_save_mapping() {
-        let mut db = setup_sqlite().await;
-        let mapping = MappingChat::from((1, 2, 3, 4));
-        
-        db.save_mapping(mapping).await.expect("Failed to save mapping");
-        let first_mapping = db.get_mapping(1)
-            .await
-            .expect("Failed to get mapping")
-            .expect("Mapping not found");
-        let second_mapping = db.get_mapping(2)
-            .await
-            .expect("Failed to get mapping")
-            .expect("Mapping not found");
-        assert_eq!(first_mapping.sender_chat, second_mapping.recipient_chat);
-        assert_eq!(first_mapping.recipient_chat, second_mapping.sender_chat);
-        assert_eq!(first_mapping.last_private, second_mapping.last_private);
-        assert_eq!(first_mapping.last_topic, second_mapping.last_topic);
+    pub async fn get_reminders(&self) -> errors::Result<Vec<Reminder>> {
+        let reminders = sqlx::query_as::<_, (i64, i64, i32, i64, String, String, i64, bool)>(
+            r#"
+               SELECT id, forum_id, thread_id, admin_id, admin_name, text, due_at, reopen
+               FROM reminders
+               ORDER BY due_at;
+               "#
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(id, forum_id, thread_id, admin_id, admin_name, text, due_at, reopen)| Reminder {
+                id,
+                forum_id,
+                thread_id,
+                admin_id: admin_id as u64,
+                admin_name,
+                text,
+                due_at,
+                reopen,
+            })
+            .collect();
+
+        Ok(reminders)
     }
 
-    #[tokio::test]
-    async fn test_drop_mapping() {
-        let mut db = setup_sqlite().await;
-        let mapping = MappingChat::from((5, 6, 7, 8));
+    pub async fn delete_reminder(&self, id: i64) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               DELETE FROM reminders
+               WHERE id = ?;
+               "#
+        )
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
 
-        let fetched_mapping = db.get_mapping(6).await;
-        assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
-        db.save_mapping(mapping).await.expect("Failed to save mapping");
-        db.drop_mapping(6).await.expect("Failed to delete mapping");
-        let fetched_mapping = db.get_mapping(6).await;
-        assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
+        Ok(())
     }
-    
-    #[tokio::test]
-    async fn test_ban_user() {
-        let mut db = setup_sqlite().await;
-        let mapping = MappingChat::from((9, 10, 11, 12));
-        
-        let _ = db.save_mapping(mapping).await;
-        let banned = db.check_ban(9).await.expect("Failed to check ban");
-        assert!(!banned);
-        db.ban_user(9).await.expect("Failed to ban user");
-        let banned = db.check_ban(9).await.expect("Failed to check ban");
-        assert!(banned);
+
+    /// Remembers the first unanswered message of the user.
+    /// SQLite is only queried when the user starts waiting,
+    /// the following messages are filtered out by Redis.
+    pub async fn mark_waiting(&mut self, private_chat: i64, forum_id: i64, thread_id: i32, since: i64) -> errors::Result<()> {
+        if !self.redis_cache.mark_waiting(private_chat).await? {
+            return Ok(());
+        }
+        sqlx::query(
+            r#"
+               INSERT OR IGNORE INTO waiting (private_chat, forum_id, thread_id, since)
+               VALUES (?, ?, ?, ?);
+               "#
+        )
+            .bind(private_chat)
+            .bind(forum_id)
+            .bind(thread_id)
+            .bind(since)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forgets that the user is waiting, f.e. when an admin answers.
+    /// Returns the timestamp of the first unanswered message, if there was one.
+    /// SQLite is the source of truth, Redis may have lost the flag after a restart.
+    pub async fn clear_waiting(&mut self, private_chat: i64) -> errors::Result<Option<i64>> {
+        self.redis_cache.clear_waiting(private_chat).await?;
+        let since = sqlx::query_scalar(
+            r#"
+               DELETE FROM waiting
+               WHERE private_chat = ?
+               RETURNING since;
+               "#
+        )
+            .bind(private_chat)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(since)
+    }
+
+    pub async fn get_waiting(&self) -> errors::Result<Vec<Waiting>> {
+        let waiting = sqlx::query_as::<_, (i64, i64, i32, i64, u32)>(
+            r#"
+               SELECT private_chat, forum_id, thread_id, since, escalation
+               FROM waiting
+               ORDER BY since;
+               "#
+        )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(private_chat, forum_id, thread_id, since, escalation)| Waiting {
+                private_chat,
+                forum_id,
+                thread_id,
+                since,
+                escalation,
+            })
+            .collect();
+
+        Ok(waiting)
+    }
+
+    /// Records the number of alerts sent about the waiting user.
+    pub async fn set_escalation(&self, private_chat: i64, escalation: u32) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               UPDATE waiting
+               SET escalation = ?
+               WHERE private_chat = ?;
+               "#
+        )
+            .bind(escalation)
+            .bind(private_chat)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Counts the event, the counters are written to SQLite in the background.
+    pub fn record_event(&self, event: Event, timestamp: i64, scheduler: &Scheduler) {
+        self.stats_buffer.record(event, timestamp, scheduler);
+    }
+
+    /// Saves the time it took admins to answer the first unanswered message.
+    pub async fn record_response_time(&self, answered_at: i64, seconds: i64) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               INSERT INTO response_times (answered_at, seconds)
+               VALUES (?, ?);
+               "#
+        )
+            .bind(answered_at)
+            .bind(seconds)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the activity between the unix timestamps `from` (inclusive) and `to` (exclusive).
+    /// The counters are kept per day, so both bounds are rounded down to days.
+    /// Counters that are not written yet are flushed first.
+    pub async fn get_stats(&self, from: i64, to: i64) -> errors::Result<Stats> {
+        self.stats_buffer.flush().await?;
+        let events = sqlx::query_as::<_, (String, i64)>(
+            r#"
+               SELECT event, SUM(count)
+               FROM daily_stats
+               WHERE day >= ? AND day < ?
+               GROUP BY event;
+               "#
+        )
+            .bind(day_of(from))
+            .bind(day_of(to))
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .filter_map(|(event, count)| Some((Event::from_name(&event)?, count)))
+            .collect();
+        let response_times = sqlx::query_scalar(
+            r#"
+               SELECT seconds
+               FROM response_times
+               WHERE answered_at >= ? AND answered_at < ?
+               ORDER BY seconds;
+               "#
+        )
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Stats { events, response_times })
+    }
+
+    /// Counts a message from (`Event::MessageIn`) or to (`Event::MessageOut`) the user,
+    /// both in the daily counters and in the user's profile.
+    pub fn record_message(&self, event: Event, private_chat: i64, timestamp: i64, scheduler: &Scheduler) {
+        self.stats_buffer.record_message(event, private_chat, timestamp, scheduler);
+    }
+
+    /// Adds the relayed text to the search index in the background.
+    pub fn record_history(&self, message: HistoryMessage, scheduler: &Scheduler) {
+        self.stats_buffer.record_history(message, scheduler);
+    }
+
+    /// Searches the relayed texts, the best matches first.
+    /// The `text` of the results is a snippet with the matched terms between `\u{2}` and `\u{3}`.
+    pub async fn search_history(&self, query: &SearchQuery, limit: u32) -> errors::Result<Vec<HistoryMessage>> {
+        self.stats_buffer.flush().await?;
+        // Every term is quoted, so the FTS5 query syntax is not available to admins
+        let terms = query.terms.iter()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let messages = sqlx::query_as::<_, (i64, i64, i32, i32, bool, i64, String)>(
+            r#"
+               SELECT private_chat, forum_id, thread_id, message_id, outgoing, sent_at,
+                      snippet(history, 0, char(2), char(3), '…', 12)
+               FROM history
+               WHERE history MATCH ?
+                 AND (? IS NULL OR private_chat = ?)
+                 AND (? IS NULL OR sent_at >= ?)
+                 AND (? IS NULL OR sent_at < ?)
+               ORDER BY rank
+               LIMIT ?;
+               "#
+        )
+            .bind(terms)
+            .bind(query.private_chat)
+            .bind(query.private_chat)
+            .bind(query.from)
+            .bind(query.from)
+            .bind(query.to)
+            .bind(query.to)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(private_chat, forum_id, thread_id, message_id, outgoing, sent_at, text)| HistoryMessage {
+                private_chat,
+                forum_id,
+                thread_id,
+                message_id,
+                outgoing,
+                sent_at,
+                text,
+            })
+            .collect();
+
+        Ok(messages)
+    }
+
+    /// Writes the pending counters, f.e. before showing them.
+    pub async fn flush_stats(&self) -> errors::Result<()> {
+        self.stats_buffer.flush().await
+    }
+
+    /// Saves the changeable fields of the profile, the counters and the card of an existing user are kept.
+    pub async fn save_user(&self, profile: &UserProfile) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               INSERT INTO users (chat_id, forum_id, thread_id, full_name, username, language_code, first_seen, last_seen, card_message_id, intake)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+               ON CONFLICT (chat_id) DO UPDATE SET
+                   forum_id = excluded.forum_id,
+                   thread_id = excluded.thread_id,
+                   full_name = excluded.full_name,
+                   username = excluded.username,
+                   language_code = excluded.language_code,
+                   card_message_id = COALESCE(excluded.card_message_id, card_message_id),
+                   intake = COALESCE(excluded.intake, intake);
+               "#
+        )
+            .bind(profile.chat_id)
+            .bind(profile.forum_id)
+            .bind(profile.thread_id)
+            .bind(&profile.full_name)
+            .bind(&profile.username)
+            .bind(&profile.language_code)
+            .bind(profile.first_seen)
+            .bind(profile.last_seen)
+            .bind(profile.card_message_id)
+            .bind(profile.intake.as_ref().map(sqlx::types::Json))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_user(&self, private_chat: i64) -> errors::Result<Option<UserProfile>> {
+        let user = sqlx::query_as::<_, UserRow>(
+            r#"
+               SELECT users.chat_id, users.forum_id, thread_id, full_name, username, language_code,
+                      first_seen, last_seen, messages_in, messages_out, card_message_id, source, intake,
+                      owner_id, owner_name
+               FROM users
+               LEFT JOIN sources ON sources.chat_id = users.chat_id
+               LEFT JOIN mapping ON mapping.private_chat = users.chat_id
+               WHERE users.chat_id = ?;
+               "#
+        )
+            .bind(private_chat)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(user_from_row);
+
+        Ok(user)
+    }
+
+    /// Saves the profile if the user changed it since the last message.
+    /// The fields are compared with the cached ones first, so SQLite is rarely queried.
+    /// Returns the previous profile if a known user changed it, so its card is outdated.
+    pub async fn update_user(&mut self, profile: &UserProfile) -> errors::Result<Option<UserProfile>> {
+        let fingerprint = profile.fingerprint();
+        if self.redis_cache.get_profile_fingerprint(profile.chat_id).await?.as_ref() == Some(&fingerprint) {
+            return Ok(None);
+        }
+        let changed = match self.get_user(profile.chat_id).await? {
+            Some(saved) if saved.same_fields(profile) => None,
+            saved => {
+                self.save_user(profile).await?;
+                saved
+            },
+        };
+        self.redis_cache.save_profile_fingerprint(profile.chat_id, &fingerprint).await?;
+
+        Ok(changed)
+    }
+
+    pub async fn set_card(&self, private_chat: i64, card_message_id: i32) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               UPDATE users
+               SET card_message_id = ?
+               WHERE chat_id = ?;
+               "#
+        )
+            .bind(card_message_id)
+            .bind(private_chat)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the user with the current topic, `None` if the user has no topic.
+    pub async fn get_topic_user(&self, private_chat: i64) -> errors::Result<Option<UserProfile>> {
+        let user = sqlx::query_as::<_, UserRow>(
+            r#"
+               SELECT users.chat_id, mapping.forum_id, mapping.topic_chat, full_name, username, language_code,
+                      first_seen, last_seen, messages_in, messages_out, card_message_id, source, intake,
+                      owner_id, owner_name
+               FROM users
+               JOIN mapping ON mapping.private_chat = users.chat_id
+               LEFT JOIN sources ON sources.chat_id = users.chat_id
+               WHERE users.chat_id = ?;
+               "#
+        )
+            .bind(private_chat)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(user_from_row);
+
+        Ok(user)
+    }
+
+    /// Finds users with an active topic by id, username or name.
+    /// The username and the name are matched by a case-insensitive substring.
+    pub async fn find_users(&self, query: &str, limit: u32) -> errors::Result<Vec<UserProfile>> {
+        let query = query.trim().trim_start_matches('@');
+        let pattern = format!(
+            "%{}%",
+            query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"),
+        );
+        let chat_id = query.parse::<i64>().ok();
+        let users = sqlx::query_as::<_, UserRow>(
+            r#"
+               SELECT users.chat_id, mapping.forum_id, mapping.topic_chat, full_name, username, language_code,
+                      first_seen, last_seen, messages_in, messages_out, card_message_id, source, intake,
+                      owner_id, owner_name
+               FROM users
+               JOIN mapping ON mapping.private_chat = users.chat_id
+               LEFT JOIN sources ON sources.chat_id = users.chat_id
+               WHERE users.chat_id = ?
+                  OR username LIKE ? ESCAPE '\'
+                  OR full_name LIKE ? ESCAPE '\'
+               ORDER BY last_seen DESC
+               LIMIT ?;
+               "#
+        )
+            .bind(chat_id)
+            .bind(&pattern)
+            .bind(&pattern)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(user_from_row)
+            .collect();
+
+        Ok(users)
+    }
+
+    /// Assigns the topic of the private chat to the admin.
+    pub async fn set_owner(&self, private_chat: i64, owner: &Owner) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               UPDATE mapping
+               SET owner_id = ?, owner_name = ?
+               WHERE private_chat = ?;
+               "#
+        )
+            .bind(owner.id)
+            .bind(&owner.name)
+            .bind(private_chat)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_owner(&self, private_chat: i64) -> errors::Result<Option<Owner>> {
+        let owner: Option<(Option<i64>, Option<String>)> = sqlx::query_as(
+            "SELECT owner_id, owner_name FROM mapping WHERE private_chat = ?;"
+        )
+            .bind(private_chat)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(owner.and_then(|(id, name)| Some(Owner { id, name: name? })))
+    }
+
+    /// Returns the users whose topics are assigned to the admin, recently active first.
+    /// Admins assigned by the username are matched by it.
+    pub async fn get_owned_users(&self, admin_id: i64, username: Option<&str>, limit: u32) -> errors::Result<Vec<UserProfile>> {
+        let users = sqlx::query_as::<_, UserRow>(
+            r#"
+               SELECT users.chat_id, mapping.forum_id, mapping.topic_chat, full_name, username, language_code,
+                      first_seen, last_seen, messages_in, messages_out, card_message_id, source, intake,
+                      owner_id, owner_name
+               FROM users
+               JOIN mapping ON mapping.private_chat = users.chat_id
+               LEFT JOIN sources ON sources.chat_id = users.chat_id
+               WHERE owner_id = ?
+                  OR (owner_id IS NULL AND owner_name = ? COLLATE NOCASE)
+               ORDER BY last_seen DESC
+               LIMIT ?;
+               "#
+        )
+            .bind(admin_id)
+            .bind(username.map(|username| format!("@{username}")))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(user_from_row)
+            .collect();
+
+        Ok(users)
+    }
+
+    /// Sets the name the admin signs the replies with, `None` removes it.
+    pub async fn set_alias(&self, admin_id: i64, alias: Option<&str>) -> errors::Result<()> {
+        let query = match alias {
+            Some(alias) => sqlx::query(
+                r#"
+                   INSERT INTO aliases (admin_id, alias)
+                   VALUES (?, ?)
+                   ON CONFLICT (admin_id) DO UPDATE SET alias = excluded.alias;
+                   "#
+            )
+                .bind(admin_id)
+                .bind(alias),
+            None => sqlx::query("DELETE FROM aliases WHERE admin_id = ?;").bind(admin_id),
+        };
+        query.execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn get_alias(&self, admin_id: i64) -> errors::Result<Option<String>> {
+        let alias = sqlx::query_scalar("SELECT alias FROM aliases WHERE admin_id = ?;")
+            .bind(admin_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(alias)
+    }
+
+    /// Sets the role of the member, `None` removes it.
+    pub async fn set_role(&self, member_id: i64, role: Option<Role>) -> errors::Result<()> {
+        let member = member_id.to_string();
+        let query = match role {
+            Some(role) => sqlx::query(
+                r#"
+                   INSERT INTO roles (member, role)
+                   VALUES (?, ?)
+                   ON CONFLICT (member) DO UPDATE SET role = excluded.role;
+                   "#
+            )
+                .bind(member)
+                .bind(role.as_str()),
+            None => sqlx::query("DELETE FROM roles WHERE member = ?;").bind(member),
+        };
+        query.execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Returns the role set with `/role`.
+    pub async fn get_role(&self, member_id: i64) -> errors::Result<Option<Role>> {
+        let role: Option<String> = sqlx::query_scalar("SELECT role FROM roles WHERE member = ?;")
+            .bind(member_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(role.as_deref().and_then(Role::from_name))
+    }
+
+    /// Remembers the username of a member who wrote in a forum, so that `/role @username` finds the id.
+    /// A username that moved to another member is taken from the previous one.
+    pub async fn save_member(&self, member_id: i64, username: Option<&str>) -> errors::Result<()> {
+        let username = username.map(str::to_ascii_lowercase);
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE members SET username = NULL WHERE username = ? AND member_id != ?;")
+            .bind(&username)
+            .bind(member_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+               INSERT INTO members (member_id, username)
+               VALUES (?, ?)
+               ON CONFLICT (member_id) DO UPDATE SET username = excluded.username;
+               "#
+        )
+            .bind(member_id)
+            .bind(&username)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Returns the id of the member who has the username now, if the member wrote in a forum.
+    pub async fn get_member_id(&self, username: &str) -> errors::Result<Option<i64>> {
+        let member_id = sqlx::query_scalar("SELECT member_id FROM members WHERE username = ?;")
+            .bind(username.to_ascii_lowercase())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(member_id)
+    }
+
+    /// Returns the role that follows from the status in the forum, if it was asked recently.
+    pub async fn get_cached_role(&mut self, forum_id: i64, member_id: i64) -> errors::Result<Option<Role>> {
+        let role = self.redis_cache.get_role(forum_id, member_id).await?;
+        Ok(role.as_deref().and_then(Role::from_name))
+    }
+
+    pub async fn cache_role(&mut self, forum_id: i64, member_id: i64, role: Role) -> errors::Result<()> {
+        self.redis_cache.save_role(forum_id, member_id, role.as_str()).await
+    }
+
+    /// Records an administrative action.
+    /// Such actions are rare, so they are written right away.
+    pub async fn audit(&self, entry: &AuditEntry) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               INSERT INTO audit_log (actor_id, actor_name, action, target, forum_id, thread_id, args, ts)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?);
+               "#
+        )
+            .bind(entry.actor_id)
+            .bind(&entry.actor_name)
+            .bind(entry.action.as_str())
+            .bind(entry.target)
+            .bind(entry.forum_id)
+            .bind(entry.thread_id)
+            .bind(&entry.args)
+            .bind(entry.ts)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the audit log, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Only the actions about the user.
+    /// * `limit` - The whole log if it is not set.
+    pub async fn get_audit(&self, target: Option<i64>, limit: Option<u32>) -> errors::Result<Vec<AuditEntry>> {
+        let rows: Vec<AuditRow> = sqlx::query_as(
+            r#"
+               SELECT actor_id, actor_name, action, target, forum_id, thread_id, args, ts
+               FROM audit_log
+               WHERE ? IS NULL OR target = ?
+               ORDER BY id DESC
+               LIMIT ?;
+               "#
+        )
+            .bind(target)
+            .bind(target)
+            .bind(limit.map_or(-1, i64::from))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let entries = rows.into_iter()
+            .filter_map(|(actor_id, actor_name, action, target, forum_id, thread_id, args, ts)| Some(AuditEntry {
+                actor_id,
+                actor_name,
+                action: AuditAction::from_name(&action)?,
+                target,
+                forum_id,
+                thread_id,
+                args,
+                ts,
+            }))
+            .collect();
+        Ok(entries)
+    }
+
+    /// Saves the `/start` payload the user came with.
+    /// Only the first one is kept, it is where the user came from.
+    pub async fn save_source(&self, private_chat: i64, source: &str, started_at: i64) -> errors::Result<()> {
+        sqlx::query(
+            r#"
+               INSERT OR IGNORE INTO sources (chat_id, source, started_at)
+               VALUES (?, ?, ?);
+               "#
+        )
+            .bind(private_chat)
+            .bind(source)
+            .bind(started_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_source(&self, private_chat: i64) -> errors::Result<Option<String>> {
+        let source = sqlx::query_scalar(
+            r#"
+               SELECT source
+               FROM sources
+               WHERE chat_id = ?;
+               "#
+        )
+            .bind(private_chat)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(source)
+    }
+
+    /// Returns the number of users that came from each source between the unix timestamps `from` and `to`,
+    /// the most popular sources first.
+    pub async fn get_source_counts(&self, from: i64, to: i64) -> errors::Result<Vec<(String, i64)>> {
+        let counts = sqlx::query_as(
+            r#"
+               SELECT source, COUNT(*) AS users
+               FROM sources
+               WHERE started_at >= ? AND started_at < ?
+               GROUP BY source
+               ORDER BY users DESC, source;
+               "#
+        )
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(counts)
+    }
+
+    /// Returns the timestamps of the user's bans.
+    pub async fn get_bans(&self, private_chat: i64) -> errors::Result<Vec<i64>> {
+        let bans = sqlx::query_scalar(
+            r#"
+               SELECT banned_at
+               FROM ban_history
+               WHERE chat_id = ?
+               ORDER BY banned_at;
+               "#
+        )
+            .bind(private_chat)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(bans)
+    }
+
+    /// Returns the number of events per day between the unix timestamps `from` and `to`.
+    /// Days without events are skipped.
+    pub async fn get_daily_counts(&self, event: Event, from: i64, to: i64) -> errors::Result<Vec<(i64, i64)>> {
+        self.stats_buffer.flush().await?;
+        let counts = sqlx::query_as(
+            r#"
+               SELECT day, count
+               FROM daily_stats
+               WHERE event = ? AND day >= ? AND day < ?
+               ORDER BY day;
+               "#
+        )
+            .bind(event.as_str())
+            .bind(day_of(from))
+            .bind(day_of(to))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(counts)
+    }
+
+    pub async fn get_totals(&self) -> errors::Result<Totals> {
+        let (active_mappings, banned) = sqlx::query_as(
+            r#"
+               SELECT
+                   (SELECT COUNT(*) FROM mapping),
+                   (SELECT COUNT(*) FROM banned);
+               "#
+        )
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(Totals { active_mappings, banned })
+    }
+
+    /// Returns the number of open topics of the forum: the ones with a mapping that are not snoozed.
+    pub async fn count_topics(&self, forum_id: i64) -> errors::Result<i64> {
+        let (count,) = sqlx::query_as(
+            r#"
+               SELECT COUNT(*)
+               FROM mapping
+               WHERE forum_id = ?
+                 AND NOT EXISTS (
+                     SELECT 1
+                     FROM reminders
+                     WHERE reminders.forum_id = mapping.forum_id
+                       AND reminders.thread_id = mapping.topic_chat
+                       AND reopen
+                 );
+               "#
+        )
+            .bind(forum_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Locks the topic for the replying admin, see `RedisAPI::lock_reply`.
+    pub async fn lock_reply(
+        &mut self,
+        forum_id: i64,
+        thread_id: i32,
+        admin_id: u64,
+        admin_name: &str,
+        window: u64,
+    ) -> errors::Result<Option<String>> {
+        self.redis_cache.lock_reply(forum_id, thread_id, admin_id, admin_name, window).await
+    }
+
+    /// Checks that the SQLite pool can serve queries.
+    pub async fn ping_sqlite(&self) -> errors::Result<()> {
+        sqlx::query("SELECT 1;").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Checks that the Redis connection is alive.
+    pub async fn ping_redis(&mut self) -> errors::Result<()> {
+        self.redis_cache.ping().await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::db::redis::tests::get_test_redis;
+    use teloxide::types::MessageId;
+    
+    /// A user in topic 1 of the forum -100 without any activity, also for the card tests.
+    pub fn profile(id: i64) -> UserProfile {
+        UserProfile {
+            chat_id: id,
+            forum_id: -100,
+            thread_id: 1,
+            full_name: "John".to_string(),
+            username: None,
+            language_code: None,
+            first_seen: 0,
+            last_seen: 0,
+            messages_in: 0,
+            messages_out: 0,
+            card_message_id: None,
+            source: None,
+            intake: None,
+            owner: None,
+        }
+    }
+
+    async fn setup_sqlite() -> Database {
+        let redis_cache = get_test_redis().await;
+        let pool = create_sqlite_pool(":memory:")
+            .await
+            .expect("Failed to create SQLite pool");
+        let sync_buffer = SyncBuffer::new(pool.clone(), redis_cache.clone(), 2);
+        let stats_buffer = StatsBuffer::new(pool.clone());
+        Database { pool, redis_cache, sync_buffer, stats_buffer }
+    }
+
+    #[tokio::test]
+    async fn test_save_mapping() {
+        let mut db = setup_sqlite().await;
+        let mapping = MappingChat::from((1000, 2, 3, 4, -100));
+        
+        db.save_mapping(mapping).await.expect("Failed to save mapping");
+        let first_mapping = db.get_mapping(1000)
+            .await
+            .expect("Failed to get mapping")
+            .expect("Mapping not found");
+        let second_mapping = db.get_topic_mapping(-100, 2)
+            .await
+            .expect("Failed to get mapping")
+            .expect("Mapping not found");
+        assert_eq!(first_mapping.sender_chat, second_mapping.recipient_chat);
+        assert_eq!(first_mapping.recipient_chat, second_mapping.sender_chat);
+        assert_eq!(first_mapping.last_private, second_mapping.last_private);
+        assert_eq!(first_mapping.last_topic, second_mapping.last_topic);
+        assert_eq!(second_mapping.forum_id.0, -100);
+    }
+
+    #[tokio::test]
+    async fn test_mapping_orientation() {
+        let mut db = setup_sqlite().await;
+        // A user id can be smaller than the thread id
+        db.save_mapping(MappingChat::from((7, 900, 1, 2, -100))).await.expect("Failed to save mapping");
+
+        let mapping = db.get_topic_mapping(-100, 900)
+            .await
+            .expect("Failed to get mapping")
+            .expect("Mapping not found");
+        assert_eq!(mapping.sender_chat.0, 900);
+        assert_eq!(mapping.private_chat().0, 7);
+        assert_eq!(mapping.topic_chat().0, 900);
+        assert_eq!(mapping.unique_id(), 7);
+
+        let mut synced = mapping;
+        synced.sync(MessageId(3), MessageId(4));
+        db.sync_buffer.extend([synced]);
+        db.sync_buffer.flush().await.expect("Failed to flush");
+        let row: (i64, i32, i32) = sqlx::query_as("SELECT topic_chat, last_private, last_topic FROM mapping WHERE private_chat = 7;")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to read mapping");
+        assert_eq!(row, (900, 3, 4));
+    }
+
+    #[tokio::test]
+    async fn test_topics_of_forums() {
+        let mut db = setup_sqlite().await;
+
+        // A mapping created before multiple forums were supported
+        sqlx::query("INSERT INTO mapping (private_chat, topic_chat, last_private, last_topic) VALUES (3000, 4, 5, 6);")
+            .execute(&db.pool)
+            .await
+            .expect("Failed to save mapping");
+        db.assign_default_forum(-100).await.expect("Failed to assign forum");
+        // The same thread in another forum
+        db.save_mapping(MappingChat::from((3001, 4, 7, 8, -200))).await.expect("Failed to save mapping");
+
+        let first = db.get_topic_mapping(-100, 4)
+            .await
+            .expect("Failed to get mapping")
+            .expect("Mapping not found");
+        let second = db.get_topic_mapping(-200, 4)
+            .await
+            .expect("Failed to get mapping")
+            .expect("Mapping not found");
+        assert_eq!(first.private_chat().0, 3000);
+        assert_eq!(second.private_chat().0, 3001);
+        assert_eq!(db.get_mapping(3001).await.expect("Failed to get mapping").map(|m| m.forum_id.0), Some(-200));
+    }
+
+    #[tokio::test]
+    async fn test_drop_mapping() {
+        let mut db = setup_sqlite().await;
+        let mapping = MappingChat::from((5000, 6, 7, 8, -100));
+
+        let fetched_mapping = db.get_topic_mapping(-100, 6).await;
+        assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
+        db.save_mapping(mapping).await.expect("Failed to save mapping");
+        db.drop_mapping(-100, 6).await.expect("Failed to delete mapping");
+        let fetched_mapping = db.get_topic_mapping(-100, 6).await;
+        assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
+        let fetched_mapping = db.get_mapping(5000).await;
+        assert!(fetched_mapping.is_ok_and(|m| m.is_none()));
+    }
+    
+    #[tokio::test]
+    async fn test_ban_user() {
+        let mut db = setup_sqlite().await;
+        let mapping = MappingChat::from((9000, 10, 11, 12, -100));
+        
+        let _ = db.save_mapping(mapping).await;
+        let banned = db.check_ban(9000).await.expect("Failed to check ban");
+        assert!(!banned);
+        db.ban_user(9000).await.expect("Failed to ban user");
+        let banned = db.check_ban(9000).await.expect("Failed to check ban");
+        assert!(banned);
+    }
+
+    #[tokio::test]
+    async fn test_replay_outbox() {
+        let mut db = setup_sqlite().await;
+        let mapping = MappingChat::from((20000, 21, 22, 23, -100));
+
+        db.save_mapping(mapping).await.expect("Failed to save mapping");
+        // A sync that was recorded, but never written to SQLite
+        db.redis_cache.save_pending_sync(MappingChat::from((20000, 21, 24, 25, -100)))
+            .await
+            .expect("Failed to save sync");
+        let replayed = db.replay_outbox().await.expect("Failed to replay outbox");
+        assert_eq!(replayed, 1);
+
+        db.redis_cache.delete_mapping(20000).await.expect("Failed to clear cache");
+        let mapping = db.get_mapping(20000)
+            .await
+            .expect("Failed to get mapping")
+            .expect("Mapping not found");
+        assert_eq!(mapping.last_private.0, 24);
+        assert_eq!(mapping.last_topic.0, 25);
+    }
+
+    #[tokio::test]
+    async fn test_sync_mapping_batch() {
+        let mut db = setup_sqlite().await;
+        let scheduler = Scheduler::new(std::time::Duration::from_secs(60));
+        let first = MappingChat::from((26000, 27, 28, 29, -100));
+        let second = MappingChat::from((30000, 31, 32, 33, -100));
+
+        db.save_mapping(first).await.expect("Failed to save mapping");
+        db.save_mapping(second).await.expect("Failed to save mapping");
+        db.sync_mapping(MappingChat::from((26000, 27, 34, 35, -100)), scheduler.clone())
+            .await
+            .expect("Failed to sync mapping");
+        assert_eq!(scheduler.pending_tasks(), 1);
+        // The second dirty mapping fills the batch and flushes both
+        db.sync_mapping(MappingChat::from((30000, 31, 36, 37, -100)).reversed(), scheduler.clone())
+            .await
+            .expect("Failed to sync mapping");
+
+        let rows: Vec<(i32, i32)> = sqlx::query_as(
+            "SELECT last_private, last_topic FROM mapping ORDER BY private_chat;"
+        )
+            .fetch_all(&db.pool)
+            .await
+            .expect("Failed to fetch mappings");
+        assert_eq!(rows, vec![(34, 35), (36, 37)]);
+    }
+
+    #[tokio::test]
+    async fn test_reminders() {
+        let db = setup_sqlite().await;
+        let mut reminder = Reminder {
+            id: 0,
+            forum_id: -100,
+            thread_id: 38,
+            admin_id: 39,
+            admin_name: "Admin".to_string(),
+            text: "check refund".to_string(),
+            due_at: 1_700_000_000,
+            reopen: false,
+        };
+
+        reminder.id = db.save_reminder(&reminder).await.expect("Failed to save reminder");
+        let reminders = db.get_reminders().await.expect("Failed to get reminders");
+        assert_eq!(reminders, vec![reminder.clone()]);
+        db.delete_reminder(reminder.id).await.expect("Failed to delete reminder");
+        assert!(db.get_reminders().await.expect("Failed to get reminders").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_waiting() {
+        let mut db = setup_sqlite().await;
+
+        db.mark_waiting(40, -100, 41, 1_700_000_000).await.expect("Failed to mark waiting");
+        // The first unanswered message is kept
+        db.mark_waiting(40, -100, 41, 1_700_000_100).await.expect("Failed to mark waiting");
+        db.set_escalation(40, 1).await.expect("Failed to set escalation");
+        let waiting = db.get_waiting().await.expect("Failed to get waiting");
+        assert_eq!(waiting, vec![Waiting { private_chat: 40, forum_id: -100, thread_id: 41, since: 1_700_000_000, escalation: 1 }]);
+
+        let since = db.clear_waiting(40).await.expect("Failed to clear waiting");
+        assert_eq!(since, Some(1_700_000_000));
+        assert_eq!(db.clear_waiting(40).await.expect("Failed to clear waiting"), None);
+        assert!(db.get_waiting().await.expect("Failed to get waiting").is_empty());
+        // The row is cleared even if Redis lost the flag
+        db.mark_waiting(42, -100, 43, 1_700_000_000).await.expect("Failed to mark waiting");
+        db.redis_cache.clear_waiting(42).await.expect("Failed to clear waiting");
+        assert_eq!(db.clear_waiting(42).await.expect("Failed to clear waiting"), Some(1_700_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let db = setup_sqlite().await;
+        let scheduler = Scheduler::new(std::time::Duration::from_secs(60));
+        let day = 24 * 60 * 60;
+        let today = 19_000 * day;
+
+        db.record_event(Event::MessageIn, today + 10, &scheduler);
+        db.record_event(Event::MessageIn, today + 20, &scheduler);
+        db.record_event(Event::MessageOut, today + 30, &scheduler);
+        db.record_event(Event::MessageIn, today - day, &scheduler);
+        db.record_response_time(today + 30, 20).await.expect("Failed to save response time");
+        db.record_response_time(today + 40, 5).await.expect("Failed to save response time");
+
+        let stats = db.get_stats(today, today + day).await.expect("Failed to get stats");
+        assert_eq!(stats.count(Event::MessageIn), 2);
+        assert_eq!(stats.count(Event::MessageOut), 1);
+        assert_eq!(stats.count(Event::Ban), 0);
+        assert_eq!(stats.response_times, vec![5, 20]);
+        assert_eq!(stats.response_percentile(50), Some(5));
+        // Flushed counters are added to the saved ones
+        db.record_event(Event::MessageIn, today + 50, &scheduler);
+        let stats = db.get_stats(today - day, today + day).await.expect("Failed to get stats");
+        assert_eq!(stats.count(Event::MessageIn), 4);
+        let daily = db.get_daily_counts(Event::MessageIn, today - day, today + day)
+            .await
+            .expect("Failed to get daily counts");
+        assert_eq!(daily, vec![(19_000 - 1, 1), (19_000, 3)]);
+    }
+
+    #[tokio::test]
+    async fn test_totals() {
+        let mut db = setup_sqlite().await;
+
+        db.save_mapping(MappingChat::from((42000, 43, 44, 45, -100))).await.expect("Failed to save mapping");
+        db.save_mapping(MappingChat::from((46000, 47, 48, 49, -100))).await.expect("Failed to save mapping");
+        db.ban_user(46000).await.expect("Failed to ban user");
+        let totals = db.get_totals().await.expect("Failed to get totals");
+        assert_eq!(totals, Totals { active_mappings: 1, banned: 1 });
+        assert_eq!(db.count_topics(-100).await.expect("Failed to count topics"), 1);
+        assert_eq!(db.count_topics(-200).await.expect("Failed to count topics"), 0);
+        // Snoozed topics are closed
+        db.save_reminder(&Reminder {
+            id: 0,
+            forum_id: -100,
+            thread_id: 43,
+            admin_id: 1,
+            admin_name: "Admin".to_string(),
+            text: String::new(),
+            due_at: 0,
+            reopen: true,
+        }).await.expect("Failed to save reminder");
+        assert_eq!(db.count_topics(-100).await.expect("Failed to count topics"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_users() {
+        let mut db = setup_sqlite().await;
+        let scheduler = Scheduler::new(std::time::Duration::from_secs(60));
+        let mut profile = UserProfile {
+            thread_id: 5,
+            language_code: Some("en".to_string()),
+            first_seen: 1_700_000_000,
+            last_seen: 1_700_000_000,
+            card_message_id: Some(52),
+            ..profile(50)
+        };
+
+        // A new user is saved, but there is no outdated card
+        assert!(db.update_user(&profile).await.expect("Failed to update user").is_none());
+        assert!(db.update_user(&profile).await.expect("Failed to update user").is_none());
+        db.record_message(Event::MessageIn, 50, 1_700_000_100, &scheduler);
+        db.record_message(Event::MessageOut, 50, 1_700_000_200, &scheduler);
+        db.flush_stats().await.expect("Failed to flush stats");
+
+        profile.username = Some("john".to_string());
+        let previous = db.update_user(&profile).await.expect("Failed to update user");
+        assert_eq!(previous.expect("Profile not changed").username, None);
+        let user = db.get_user(50).await.expect("Failed to get user").expect("User not found");
+        assert_eq!(user.username.as_deref(), Some("john"));
+        assert_eq!((user.last_seen, user.messages_in, user.messages_out), (1_700_000_100, 1, 1));
+        assert_eq!(user.card_message_id, Some(52));
+
+        db.save_mapping(MappingChat::from((50, 5, 53, 54, -100))).await.expect("Failed to save mapping");
+        db.ban_user(50).await.expect("Failed to ban user");
+        assert_eq!(db.get_bans(50).await.expect("Failed to get bans").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_find_users() {
+        let mut db = setup_sqlite().await;
+        for (chat_id, thread_id, full_name, username) in [
+            (60, 1, "John Doe", Some("john_doe")),
+            (62, 2, "Jane 100%", None),
+            (64, 3, "Johnny", Some("johnny")),
+        ] {
+            db.save_mapping(MappingChat::from((chat_id, thread_id as i64, 1, 1, -100))).await.expect("Failed to save mapping");
+            db.save_user(&UserProfile {
+                thread_id,
+                full_name: full_name.to_string(),
+                username: username.map(str::to_string),
+                last_seen: chat_id,
+                ..profile(chat_id)
+            }).await.expect("Failed to save user");
+        }
+        db.drop_mapping(-100, 3).await.expect("Failed to drop mapping");
+
+        let ids = |users: Vec<UserProfile>| users.into_iter().map(|user| user.chat_id).collect::<Vec<_>>();
+        assert_eq!(ids(db.find_users("john", 10).await.expect("Failed to find")), vec![60]);
+        assert_eq!(ids(db.find_users("@JOHN_", 10).await.expect("Failed to find")), vec![60]);
+        assert_eq!(ids(db.find_users("100%", 10).await.expect("Failed to find")), vec![62]);
+        assert_eq!(ids(db.find_users("62", 10).await.expect("Failed to find")), vec![62]);
+        assert!(db.find_users("nobody", 10).await.expect("Failed to find").is_empty());
+        assert_eq!(db.get_topic_user(62).await.expect("Failed to get user").map(|user| user.thread_id), Some(2));
+        assert!(db.get_topic_user(64).await.expect("Failed to get user").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_owners() {
+        let mut db = setup_sqlite().await;
+        for (chat_id, thread_id) in [(90, 1), (91, 2), (92, 3)] {
+            db.save_mapping(MappingChat::from((chat_id, thread_id, 1, 1, -100))).await.expect("Failed to save mapping");
+            db.save_user(&UserProfile {
+                thread_id: thread_id as i32,
+                last_seen: chat_id,
+                ..profile(chat_id)
+            }).await.expect("Failed to save user");
+        }
+        let alice = Owner { id: Some(7), name: "@alice".to_string() };
+        let bob = Owner { id: None, name: "@Bob".to_string() };
+        db.set_owner(90, &alice).await.expect("Failed to set owner");
+        db.set_owner(91, &bob).await.expect("Failed to set owner");
+        db.set_owner(92, &alice).await.expect("Failed to set owner");
+        db.drop_mapping(-100, 3).await.expect("Failed to drop mapping");
+
+        assert_eq!(db.get_owner(90).await.expect("Failed to get owner"), Some(alice.clone()));
+        assert_eq!(db.get_user(91).await.expect("Failed to get user").and_then(|user| user.owner), Some(bob));
+        let ids = |users: Vec<UserProfile>| users.into_iter().map(|user| user.chat_id).collect::<Vec<_>>();
+        assert_eq!(ids(db.get_owned_users(7, Some("alice"), 10).await.expect("Failed to get topics")), vec![90]);
+        assert_eq!(ids(db.get_owned_users(8, Some("bob"), 10).await.expect("Failed to get topics")), vec![91]);
+        assert!(db.get_owned_users(8, None, 10).await.expect("Failed to get topics").is_empty());
+        // The owner is dropped with the mapping
+        assert_eq!(db.get_owner(92).await.expect("Failed to get owner"), None);
+    }
+
+    #[tokio::test]
+    async fn test_stats_flushed_on_shutdown() {
+        let db = setup_sqlite().await;
+        let mut scheduler = Scheduler::new(std::time::Duration::from_secs(60));
+
+        db.record_event(Event::NewUser, 1_700_000_000, &scheduler);
+        scheduler.complete_all().await;
+        let (count,): (i64,) = sqlx::query_as("SELECT count FROM daily_stats WHERE event = 'new_user';")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Counter not written");
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_history() {
+        let db = setup_sqlite().await;
+        let scheduler = Scheduler::new(std::time::Duration::from_secs(60));
+        for (private_chat, message_id, sent_at, text) in [
+            (70, 1, 1_000, "Where is my refund?"),
+            (70, 2, 2_000, "The refund was sent yesterday"),
+            (71, 3, 3_000, "I want a \"refund\" too"),
+            (71, 4, 4_000, "Thanks"),
+        ] {
+            db.record_history(HistoryMessage {
+                private_chat,
+                forum_id: -100,
+                thread_id: 72,
+                message_id,
+                outgoing: message_id == 2,
+                sent_at,
+                text: text.to_string(),
+            }, &scheduler);
+        }
+
+        let search = |terms: &[&str], private_chat, from, to| SearchQuery {
+            terms: terms.iter().map(|term| term.to_string()).collect(),
+            private_chat,
+            from,
+            to,
+        };
+        let ids = |messages: Vec<HistoryMessage>| {
+            let mut ids = messages.into_iter().map(|message| message.message_id).collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        let found = db.search_history(&search(&["refund"], None, None, None), 10).await.expect("Failed to search");
+        assert_eq!(ids(found.clone()), vec![1, 2, 3]);
+        assert!(found.iter().any(|message| message.text == "Where is my \u{2}refund\u{3}?"));
+        let found = db.search_history(&search(&["refund"], Some(70), Some(1_500), None), 10).await.expect("Failed to search");
+        assert_eq!(ids(found), vec![2]);
+        let found = db.search_history(&search(&["refund", "too"], None, None, Some(3_500)), 10).await.expect("Failed to search");
+        assert_eq!(ids(found), vec![3]);
+        // Quotes and operators are matched as plain text
+        let found = db.search_history(&search(&["\"refund", "OR"], None, None, None), 10).await.expect("Failed to search");
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sources() {
+        let mut db = setup_sqlite().await;
+
+        db.save_source(80, "website", 1_000).await.expect("Failed to save source");
+        // The first source is kept
+        db.save_source(80, "ads", 2_000).await.expect("Failed to save source");
+        db.save_source(81, "ads", 3_000).await.expect("Failed to save source");
+        db.save_source(82, "ads", 4_000).await.expect("Failed to save source");
+        assert_eq!(db.get_source(80).await.expect("Failed to get source").as_deref(), Some("website"));
+        assert_eq!(db.get_source(83).await.expect("Failed to get source"), None);
+        let counts = db.get_source_counts(0, 3_500).await.expect("Failed to count sources");
+        assert_eq!(counts, vec![("ads".to_string(), 1), ("website".to_string(), 1)]);
+
+        let mut profile = UserProfile { thread_id: 84, ..profile(80) };
+        db.update_user(&profile).await.expect("Failed to update user");
+        profile.source = Some("website".to_string());
+        assert_eq!(db.get_user(80).await.expect("Failed to get user"), Some(profile));
+    }
+
+    #[tokio::test]
+    async fn test_aliases() {
+        let db = setup_sqlite().await;
+
+        assert_eq!(db.get_alias(7).await.expect("Failed to get alias"), None);
+        db.set_alias(7, Some("Anna")).await.expect("Failed to set alias");
+        db.set_alias(7, Some("Anna from support")).await.expect("Failed to set alias");
+        assert_eq!(db.get_alias(7).await.expect("Failed to get alias").as_deref(), Some("Anna from support"));
+        db.set_alias(7, None).await.expect("Failed to remove alias");
+        assert_eq!(db.get_alias(7).await.expect("Failed to get alias"), None);
+    }
+
+    #[tokio::test]
+    async fn test_roles() {
+        let mut db = setup_sqlite().await;
+
+        db.set_role(7, Some(Role::Admin)).await.expect("Failed to set role");
+        assert_eq!(db.get_role(7).await.expect("Failed to get role"), Some(Role::Admin));
+        db.set_role(7, None).await.expect("Failed to remove role");
+        assert_eq!(db.get_role(7).await.expect("Failed to get role"), None);
+
+        assert_eq!(db.get_member_id("alice").await.expect("Failed to get member"), None);
+        db.save_member(7, Some("Alice")).await.expect("Failed to save member");
+        assert_eq!(db.get_member_id("ALICE").await.expect("Failed to get member"), Some(7));
+        db.set_role(7, Some(Role::Observer)).await.expect("Failed to set role");
+        // Whoever takes the username later does not get the role
+        db.save_member(9, Some("alice")).await.expect("Failed to save member");
+        assert_eq!(db.get_member_id("alice").await.expect("Failed to get member"), Some(9));
+        assert_eq!(db.get_role(9).await.expect("Failed to get role"), None);
+        db.save_member(7, None).await.expect("Failed to save member");
+        assert_eq!(db.get_member_id("alice").await.expect("Failed to get member"), Some(9));
+
+        assert_eq!(db.get_cached_role(-100, 8).await.expect("Failed to get role"), None);
+        db.cache_role(-100, 8, Role::Agent).await.expect("Failed to cache role");
+        assert_eq!(db.get_cached_role(-100, 8).await.expect("Failed to get role"), Some(Role::Agent));
+    }
+
+    #[tokio::test]
+    async fn test_audit() {
+        let db = setup_sqlite().await;
+        let entry = |action, target, ts| AuditEntry {
+            actor_id: 7,
+            actor_name: "Alice".to_string(),
+            action,
+            target,
+            forum_id: -100,
+            thread_id: target.map(|_| 3),
+            args: None,
+            ts,
+        };
+
+        db.audit(&entry(AuditAction::Assign, Some(90), 1_000)).await.expect("Failed to audit");
+        db.audit(&entry(AuditAction::Role, None, 2_000)).await.expect("Failed to audit");
+        db.audit(&entry(AuditAction::Ban, Some(90), 3_000)).await.expect("Failed to audit");
+        db.audit(&entry(AuditAction::DropTopic, Some(91), 4_000)).await.expect("Failed to audit");
+
+        let log = db.get_audit(None, None).await.expect("Failed to get audit log");
+        assert_eq!(log.iter().map(|entry| entry.ts).collect::<Vec<_>>(), vec![4_000, 3_000, 2_000, 1_000]);
+        let log = db.get_audit(Some(90), Some(1)).await.expect("Failed to get audit log");
+        assert_eq!(log, vec![entry(AuditAction::Ban, Some(90), 3_000)]);
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let mut db = setup_sqlite().await;
+
+        db.ping_sqlite().await.expect("SQLite is not available");
+        db.ping_redis().await.expect("Redis is not available");
     }
 }
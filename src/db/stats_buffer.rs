@@ -0,0 +1,199 @@
+use crate::db::models::{Event, HistoryMessage};
+use crate::errors;
+use crate::scheduler::{Namespace, RetryPolicy, Scheduler};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Counters are flushed by their own task, it is completed on shutdown.
+const STATS: Namespace = Namespace::new("stats", true);
+const FLUSH_TASK_ID: u64 = 0;
+/// Texts kept in memory while SQLite is unavailable, the oldest ones are dropped over it.
+const MAX_HISTORY: usize = 10_000;
+
+/// Returns the number of the day since the unix epoch.
+pub fn day_of(timestamp: i64) -> i64 {
+    timestamp.div_euclid(24 * 60 * 60)
+}
+
+/// Messages of a user that are not counted in SQLite yet.
+#[derive(Default, Clone, Copy)]
+struct Activity {
+    last_seen: Option<i64>,
+    messages_in: i64,
+    messages_out: i64,
+}
+
+#[derive(Default)]
+struct Pending {
+    counts: HashMap<(i64, Event), i64>,
+    activity: HashMap<i64, Activity>,
+    history: Vec<HistoryMessage>,
+    flush_scheduled: bool,
+}
+
+/// Collects the daily counters, the users' activity and the texts for the search in memory
+/// and adds them to SQLite in one transaction, the same way `SyncBuffer` does with mappings.
+#[derive(Clone)]
+pub struct StatsBuffer {
+    pool: SqlitePool,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl StatsBuffer {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool, pending: Arc::new(Mutex::new(Pending::default())) }
+    }
+
+    /// Counts the event at `timestamp`, the flush is scheduled on the first one.
+    pub fn record(&self, event: Event, timestamp: i64, scheduler: &Scheduler) {
+        let schedule = {
+            let mut pending = self.pending.lock().unwrap();
+            *pending.counts.entry((day_of(timestamp), event)).or_default() += 1;
+            !std::mem::replace(&mut pending.flush_scheduled, true)
+        };
+        if schedule {
+            self.schedule_flush(scheduler);
+        }
+    }
+
+    /// Counts a message from (`Event::MessageIn`) or to (`Event::MessageOut`) the user.
+    pub fn record_message(&self, event: Event, private_chat: i64, timestamp: i64, scheduler: &Scheduler) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            let activity = pending.activity.entry(private_chat).or_default();
+            if event == Event::MessageOut {
+                activity.messages_out += 1;
+            } else {
+                activity.messages_in += 1;
+                activity.last_seen = activity.last_seen.max(Some(timestamp));
+            }
+        }
+        self.record(event, timestamp, scheduler);
+    }
+
+    /// Adds the relayed text to the search index.
+    pub fn record_history(&self, message: HistoryMessage, scheduler: &Scheduler) {
+        let schedule = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.history.push(message);
+            cap_history(&mut pending.history);
+            !std::mem::replace(&mut pending.flush_scheduled, true)
+        };
+        if schedule {
+            self.schedule_flush(scheduler);
+        }
+    }
+
+    /// The flush is completed on shutdown, see `STATS`.
+    fn schedule_flush(&self, scheduler: &Scheduler) {
+        let buffer = self.clone();
+        // A failed flush stays in the buffer, so every attempt writes it again
+        scheduler.add_retrying_task_in(STATS, FLUSH_TASK_ID, RetryPolicy::default(), move || {
+            let buffer = buffer.clone();
+            async move { buffer.flush().await }
+        });
+    }
+
+    /// Adds the pending counters to SQLite.
+    /// On failure they are returned to the buffer and written by the next flush.
+    pub async fn flush(&self) -> errors::Result<()> {
+        let (counts, activity, history): (Vec<_>, Vec<_>, Vec<_>) = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.flush_scheduled = false;
+            (
+                pending.counts.drain().collect(),
+                pending.activity.drain().collect(),
+                pending.history.drain(..).collect(),
+            )
+        };
+        if counts.is_empty() && activity.is_empty() && history.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = self.write(&counts, &activity, &history).await {
+            let mut pending = self.pending.lock().unwrap();
+            for (key, count) in counts {
+                *pending.counts.entry(key).or_default() += count;
+            }
+            for (private_chat, failed) in activity {
+                let activity = pending.activity.entry(private_chat).or_default();
+                activity.last_seen = activity.last_seen.max(failed.last_seen);
+                activity.messages_in += failed.messages_in;
+                activity.messages_out += failed.messages_out;
+            }
+            pending.history.splice(0..0, history);
+            cap_history(&mut pending.history);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    async fn write(
+        &self,
+        counts: &[((i64, Event), i64)],
+        activity: &[(i64, Activity)],
+        history: &[HistoryMessage],
+    ) -> errors::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for ((day, event), count) in counts {
+            sqlx::query(
+                r#"
+                   INSERT INTO daily_stats (day, event, count)
+                   VALUES (?, ?, ?)
+                   ON CONFLICT (day, event) DO UPDATE SET count = count + excluded.count;
+                   "#
+            )
+                .bind(day)
+                .bind(event.as_str())
+                .bind(count)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for (private_chat, activity) in activity {
+            sqlx::query(
+                r#"
+                   UPDATE users
+                   SET last_seen = MAX(last_seen, COALESCE(?, last_seen)),
+                       messages_in = messages_in + ?,
+                       messages_out = messages_out + ?
+                   WHERE chat_id = ?;
+                   "#
+            )
+                .bind(activity.last_seen)
+                .bind(activity.messages_in)
+                .bind(activity.messages_out)
+                .bind(private_chat)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for message in history {
+            sqlx::query(
+                r#"
+                   INSERT INTO history (text, private_chat, forum_id, thread_id, message_id, outgoing, sent_at)
+                   VALUES (?, ?, ?, ?, ?, ?, ?);
+                   "#
+            )
+                .bind(&message.text)
+                .bind(message.private_chat)
+                .bind(message.forum_id)
+                .bind(message.thread_id)
+                .bind(message.message_id)
+                .bind(message.outgoing)
+                .bind(message.sent_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+fn cap_history(history: &mut Vec<HistoryMessage>) {
+    if history.len() > MAX_HISTORY {
+        let dropped = history.len() - MAX_HISTORY;
+        history.drain(..dropped);
+        tracing::warn!("Dropped {dropped} texts that were not added to the search");
+    }
+}
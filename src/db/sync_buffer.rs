@@ -0,0 +1,160 @@
+use crate::db::models::MappingChat;
+use crate::db::redis::RedisAPI;
+use crate::errors;
+use crate::metrics::METRICS;
+use crate::scheduler::{Namespace, RetryPolicy, Scheduler};
+use sqlx::{Executor, Sqlite, SqlitePool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::time::Instant;
+
+/// The flush has its own namespace, so it never replaces other tasks.
+const SYNC: Namespace = Namespace::new("sync", true);
+const FLUSH_TASK_ID: u64 = 0;
+
+#[derive(Default)]
+struct Dirty {
+    mappings: HashMap<i64, MappingChat>,
+    flush_scheduled: bool,
+}
+
+/// A write-behind buffer for mapping syncs.
+/// Only the latest state of each mapping is kept,
+/// and all of them are written to SQLite in one transaction.
+///
+/// # Fields
+///
+/// * `dirty` - Mappings that are not written yet, by their unique id.
+/// * `flush_lock` - Allows only one flush at a time.
+/// * `batch_size` - Number of dirty mappings that triggers an immediate flush.
+#[derive(Clone)]
+pub struct SyncBuffer {
+    pool: SqlitePool,
+    redis_cache: RedisAPI,
+    dirty: Arc<Mutex<Dirty>>,
+    flush_lock: Arc<tokio::sync::Mutex<()>>,
+    batch_size: usize,
+}
+
+impl SyncBuffer {
+    pub fn new(pool: SqlitePool, redis_cache: RedisAPI, batch_size: usize) -> Self {
+        Self {
+            pool,
+            redis_cache,
+            dirty: Arc::new(Mutex::new(Dirty::default())),
+            flush_lock: Arc::new(tokio::sync::Mutex::new(())),
+            batch_size,
+        }
+    }
+
+    /// Marks the mapping as dirty.
+    /// The flush is scheduled on the first dirty mapping,
+    /// or is done right away when the batch is full.
+    pub async fn push(&self, mapping: MappingChat, scheduler: &Scheduler) -> errors::Result<()> {
+        let (schedule, full) = {
+            let mut dirty = self.dirty.lock().unwrap();
+            dirty.mappings.insert(mapping.unique_id(), mapping);
+            let schedule = !dirty.flush_scheduled;
+            dirty.flush_scheduled = true;
+            (schedule, dirty.mappings.len() >= self.batch_size)
+        };
+        if schedule {
+            let buffer = self.clone();
+            // A failed batch stays in the buffer, so every attempt flushes it again.
+            // SQLite may be busy or locked for a while, f.e. during a backup
+            scheduler.add_retrying_task_in(SYNC, FLUSH_TASK_ID, RetryPolicy::default(), move || {
+                let buffer = buffer.clone();
+                async move { buffer.flush().await.map(|_| ()) }
+            });
+        }
+        if full {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds mappings without scheduling a flush, f.e. the ones replayed from the outbox.
+    pub fn extend(&self, mappings: impl IntoIterator<Item = MappingChat>) {
+        let mut dirty = self.dirty.lock().unwrap();
+        for mapping in mappings {
+            dirty.mappings.insert(mapping.unique_id(), mapping);
+        }
+    }
+
+    /// Forgets the mapping, f.e. when it is deleted.
+    /// Returns `true` if it was waiting to be written.
+    pub fn cancel(&self, mapping: MappingChat) -> bool {
+        self.dirty.lock().unwrap().mappings.remove(&mapping.unique_id()).is_some()
+    }
+
+    /// Writes all dirty mappings in one transaction and removes them from the outbox.
+    /// On failure the batch is returned to the buffer, unless it was overwritten by newer syncs.
+    /// Returns the number of written mappings.
+    pub async fn flush(&self) -> errors::Result<usize> {
+        let _guard = self.flush_lock.lock().await;
+        let batch: Vec<MappingChat> = {
+            let mut dirty = self.dirty.lock().unwrap();
+            dirty.flush_scheduled = false;
+            dirty.mappings.drain().map(|(_, mapping)| mapping).collect()
+        };
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let started = Instant::now();
+        if let Err(e) = self.write_batch(&batch).await {
+            let mut dirty = self.dirty.lock().unwrap();
+            for mapping in batch {
+                dirty.mappings.entry(mapping.unique_id()).or_insert(mapping);
+            }
+            return Err(e);
+        }
+        METRICS.sync_flush_duration.observe(started.elapsed().as_secs_f64());
+        METRICS.sync_flush_batch_size.observe(batch.len() as f64);
+
+        let mut redis_cache = self.redis_cache.clone();
+        for mapping in batch.iter() {
+            if let Err(e) = redis_cache.ack_sync(*mapping).await {
+                tracing::warn!("Failed to remove the sync from the outbox: {e}");
+            }
+        }
+        tracing::info!("Successfully synchronized {} mappings", batch.len());
+
+        Ok(batch.len())
+    }
+
+    async fn write_batch(&self, batch: &[MappingChat]) -> errors::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for mapping in batch {
+            // The mapping was dropped after the sync, f.e. when the topic was deleted
+            if !update_mapping(&mut *tx, *mapping).await? {
+                tracing::warn!("No mapping to synchronize for {}", mapping.private_chat());
+            }
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Returns `false` if there is no mapping of the user.
+async fn update_mapping<'e, E>(executor: E, mapping: MappingChat) -> errors::Result<bool>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let result = sqlx::query(
+        r#"
+           UPDATE mapping
+           SET last_private = ?, last_topic = ?
+           WHERE private_chat = ?;
+           "#
+    )
+        .bind(mapping.last_private.0)
+        .bind(mapping.last_topic.0)
+        .bind(mapping.private_chat().0)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
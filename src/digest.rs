@@ -0,0 +1,163 @@
+use crate::config::DigestPeriod;
+use crate::db::{Database, Event, Stats, Waiting};
+use crate::errors;
+use crate::utils::{format_duration, topic_link, unix_now};
+use crate::Bot;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{MessageId, ThreadId};
+use tokio_util::sync::CancellationToken;
+
+const DAY: i64 = 24 * 60 * 60;
+/// The unix epoch was on Thursday, the first Monday is the 4th day.
+const FIRST_MONDAY: i64 = 4 * DAY;
+/// Number of the longest-waiting users listed in the digest.
+const LONGEST_WAITING: usize = 5;
+
+impl DigestPeriod {
+    fn length(&self) -> i64 {
+        match self {
+            DigestPeriod::Daily => DAY,
+            DigestPeriod::Weekly => 7 * DAY,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            DigestPeriod::Daily => "Daily digest",
+            DigestPeriod::Weekly => "Weekly digest",
+        }
+    }
+
+    /// Returns the start of the next period after `now`: midnight UTC, or Monday midnight UTC.
+    fn next_start(&self, now: i64) -> i64 {
+        let length = self.length();
+        FIRST_MONDAY + ((now - FIRST_MONDAY).div_euclid(length) + 1) * length
+    }
+}
+
+/// Posts the digest into every forum at the end of every period until `shutdown` is cancelled.
+/// `forums` starts with the default forum, the only one where `thread_id` is used.
+/// Periods that ended while the bot was down are not reported.
+pub async fn watch(
+    bot: Bot,
+    db: Database,
+    forums: Vec<ChatId>,
+    period: DigestPeriod,
+    thread_id: Option<ThreadId>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        let end = period.next_start(unix_now());
+        let wait = Duration::from_secs((end - unix_now()).max(0) as u64);
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(wait) => {
+                if let Err(e) = post(&bot, &db, &forums, period, thread_id, end).await {
+                    tracing::error!("Failed to post the digest: {e}");
+                }
+            }
+        }
+    }
+}
+
+async fn post(
+    bot: &Bot,
+    db: &Database,
+    forums: &[ChatId],
+    period: DigestPeriod,
+    thread_id: Option<ThreadId>,
+    end: i64,
+) -> errors::Result<()> {
+    let stats = db.get_stats(end - period.length(), end).await?;
+    let waiting = db.get_waiting().await?;
+    for (i, &forum_id) in forums.iter().enumerate() {
+        // Every forum lists the users waiting in it
+        let forum_waiting = waiting.iter()
+            .filter(|user| user.forum_id == forum_id.0)
+            .copied()
+            .collect::<Vec<_>>();
+        let mut request = bot.send_message(forum_id, digest_text(period, &stats, &forum_waiting, end));
+        if let Some(thread_id) = thread_id.filter(|_| i == 0) {
+            request = request.message_thread_id(thread_id);
+        }
+        // A forum the bot was removed from must not block the others
+        if let Err(e) = request.await {
+            tracing::warn!("Failed to post the digest into {}: {e}", forum_id.0);
+            continue;
+        }
+        tracing::info!("Digest posted into {}", forum_id.0);
+    }
+
+    Ok(())
+}
+
+fn digest_text(period: DigestPeriod, stats: &Stats, waiting: &[Waiting], now: i64) -> String {
+    let median = stats.response_percentile(50)
+        .map(|secs| format_duration(Duration::from_secs(secs as u64)))
+        .unwrap_or_else(|| "—".to_string());
+    let mut text = format!(
+        "📊 <b>{}</b>\
+        \n\n👤 New users: {} \
+        \n📥 Messages in: {} \
+        \n📤 Messages out: {} \
+        \n🗄 Topics closed: {} \
+        \n🚫 Bans: {} \
+        \n⏱ Median first response: {median}",
+        period.title(),
+        stats.count(Event::NewUser),
+        stats.count(Event::MessageIn),
+        stats.count(Event::MessageOut),
+        stats.count(Event::TopicClosed),
+        stats.count(Event::Ban),
+    );
+    if !waiting.is_empty() {
+        text.push_str("\n\n⏳ Longest waiting:");
+        // Waiting users are sorted by the time of their first unanswered message
+        for user in waiting.iter().take(LONGEST_WAITING) {
+            let waited = Duration::from_secs(((now - user.since).max(0) / 60 * 60) as u64);
+            text.push_str(&format!(
+                "\n• <a href=\"{}\">{}</a> — {}",
+                topic_link(ChatId(user.forum_id), user.thread_id),
+                user.private_chat,
+                format_duration(waited),
+            ));
+        }
+    }
+    text
+}
+
+/// Converts the configured topic id of the default forum, `None` posts into General.
+pub fn digest_thread(thread_id: Option<i32>) -> Option<ThreadId> {
+    thread_id.map(|id| ThreadId(MessageId(id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_start() {
+        // Wednesday, 2024-01-03 12:00 UTC
+        let now = 1_704_283_200;
+        assert_eq!(DigestPeriod::Daily.next_start(now), 1_704_326_400);  // Thursday 00:00
+        assert_eq!(DigestPeriod::Weekly.next_start(now), 1_704_672_000);  // Monday 00:00
+        assert_eq!(DigestPeriod::Daily.next_start(1_704_326_400), 1_704_326_400 + DAY);
+    }
+
+    #[test]
+    fn test_digest_text() {
+        let stats = Stats {
+            events: [(Event::MessageIn, 7), (Event::Ban, 1)].into(),
+            response_times: vec![60, 300, 900],
+        };
+        let waiting = vec![Waiting { private_chat: 1, forum_id: -100123456789, thread_id: 42, since: 0, escalation: 0 }];
+
+        let text = digest_text(DigestPeriod::Daily, &stats, &waiting, 2 * 60 * 60);
+        assert!(text.starts_with("📊 <b>Daily digest</b>"));
+        assert!(text.contains("📥 Messages in: 7"));
+        assert!(text.contains("🚫 Bans: 1"));
+        assert!(text.contains("⏱ Median first response: 5m"));
+        assert!(text.contains("<a href=\"https://t.me/c/123456789/42\">1</a> — 2h"));
+    }
+}
@@ -10,6 +10,8 @@ pub enum ConfigError {
     EnvFile(#[from] dotenvy::Error),
     #[error("Incorrect data: {0}")]
     Invalid(&'static str),
+    #[error("Incorrect intake form: {0}")]
+    IntakeForm(String),
 }
 
 #[derive(Error, Debug)]
@@ -22,4 +24,8 @@ pub enum Error {
     Redis(#[from] redis::RedisError),
     #[error(transparent)]
     ParseInt(#[from] std::num::ParseIntError),
+    #[error(transparent)]
+    Telegram(#[from] teloxide::RequestError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
 }
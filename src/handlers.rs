@@ -1,4 +1,22 @@
-use crate::db::{Database, MappingChat};
+// Handlers receive every dependency from dptree as a separate argument
+#![allow(clippy::too_many_arguments)]
+
+use crate::assign;
+use crate::audit;
+use crate::card;
+use crate::collision::{self, ReplyLock};
+use crate::config::StartTexts;
+use crate::db::{AuditAction, AuditEntry, Database, DialogueStorage, Event, HistoryMessage, Intake, MappingChat, Owner, Reminder, UserProfile};
+use crate::intake::{self, FirstMessage, IntakeDialogue, IntakeForm, IntakeState};
+use crate::metrics::METRICS;
+use crate::ratelimit::{RateLimiter, Verdict};
+use crate::reminders;
+use crate::roles::{self, Permissions, Role};
+use crate::routing::{self, Applicant, Routing};
+use crate::search;
+use crate::signature::{self, Signatures};
+use crate::stats;
+use crate::utils;
 use crate::Bot;
 use rand::{prelude::SliceRandom, thread_rng};
 use teloxide::{
@@ -6,32 +24,30 @@ use teloxide::{
     macros::BotCommands,
     types::{
         ChatId, 
-        InlineKeyboardButton, 
+        ForumTopic,
         InlineKeyboardMarkup, 
-        LinkPreviewOptions, 
         MessageId, 
         ReplyParameters, 
         ThreadId,
     },
     prelude::*,
 };
-use tracing::instrument;
+use tracing::{field::Empty, instrument, Span};
 use crate::scheduler::Scheduler;
 use std::env;
 use teloxide::types::{MessageKind, User};
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
+use teloxide::utils::html;
 
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
-const TOPIC_ICON_COLOR: [u32; 6] = [  // https://core.telegram.org/bots/api#createforumtopic
-    7322096, 16766590, 13338331, 9367192, 16749490, 16478047,
-];
-const LINK_PREVIEW_OPTIONS: LinkPreviewOptions = LinkPreviewOptions {
-    is_disabled: true,
-    url: None,
-    prefer_small_media: false,
-    prefer_large_media: false,
-    show_above_text: false,
-};
+/// Number of users listed by `/find`.
+const FIND_LIMIT: u32 = 10;
+/// Number of messages listed by `/search`.
+const SEARCH_LIMIT: u32 = 10;
+/// Number of topics listed by `/mine`.
+const MINE_LIMIT: u32 = 20;
+/// Number of entries listed by `/audit`.
+const AUDIT_LIMIT: u32 = 20;
 static START_COMMAND: LazyLock<String> = LazyLock::new(|| {
     env::var("START_COMMAND").expect("env var START_COMMAND must be set")
 });
@@ -42,9 +58,9 @@ static HELP_COMMAND: LazyLock<String> = LazyLock::new(|| {
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "snake_case")]
 pub enum PublicCommand {
-    /// Start
+    /// Start, with an optional deep-link payload
     #[command(description = "start")]
-    Start,
+    Start(String),
     /// Help
     #[command(description = "help")]
     Help,
@@ -56,28 +72,95 @@ pub enum AdminCommand {
     /// Drop topic
     #[command(description = "Drop the current topic")]
     DropTopic(String),
+    /// Remind about the topic
+    #[command(description = "Remind about the topic, f.e. /remind 2h check refund")]
+    Remind(String),
+    /// Snooze topic
+    #[command(description = "Close the topic and reopen it later, f.e. /snooze 1d")]
+    Snooze(String),
+    /// Show the user card
+    #[command(description = "Show the card of the user")]
+    Info,
+    /// Assign the topic
+    #[command(description = "Assign the topic to an admin, f.e. /assign @alice")]
+    Assign(String),
+    /// Take the topic
+    #[command(description = "Assign the topic to yourself")]
+    Take,
+}
+
+/// Admin commands that are not bound to a topic and also work in General.
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "snake_case")]
+pub enum ForumCommand {
+    /// Show statistics
+    #[command(description = "Show statistics for the period, f.e. /stats 30d")]
+    Stats(String),
+    /// Find users
+    #[command(description = "Find users by id, username or name, f.e. /find john")]
+    Find(String),
+    /// Link to the topic
+    #[command(description = "Link to the topic of the user, f.e. /topic 123456789")]
+    Topic(String),
+    /// Search messages
+    #[command(description = "Search messages, f.e. /search refund user:123456789 from:2024-01-01 to:2024-01-31")]
+    Search(String),
+    /// List own topics
+    #[command(description = "List the topics assigned to you")]
+    Mine,
+    /// Set the signature
+    #[command(description = "Sign your replies with the alias, f.e. /alias Anna, /alias off to remove it")]
+    Alias(String),
+    /// Set the role
+    #[command(description = "Set the role of a member, f.e. /role @alice agent")]
+    Role(String),
+    /// Show the audit log
+    #[command(description = "Show recent administrative actions, f.e. /audit 123456789 for the user")]
+    Audit(String),
+}
+
+impl ForumCommand {
+    /// Observers only read, the commands show users and messages to agents and above.
+    pub fn required_role(&self) -> Role {
+        match self {
+            ForumCommand::Role(_) => Role::Owner,
+            ForumCommand::Audit(_) => Role::Admin,
+            _ => Role::Agent,
+        }
+    }
 }
 
 pub fn handler_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
     dptree::entry()
         .branch(Update::filter_message()
-            .branch(dptree::entry()
+            // Commands count towards the rate limit too, so that a flooder gets no answers
+            .branch(dptree::filter(|msg: Message| msg.chat.is_private())
+                .filter_async(rate_limit)
+                .branch(dptree::entry()
+                    .filter_command::<PublicCommand>()
+                    .endpoint(public_command_handler)
+                )
+                .branch(dptree::filter_map(|msg: Message| msg.from)
+                    .endpoint(private_handler)
+                )
+            )
+            .branch(dptree::filter(|msg: Message| !msg.chat.is_private())
                 .filter_command::<PublicCommand>()
                 .endpoint(public_command_handler)
             )
-            .branch(
-                dptree::filter(|msg: Message| msg.chat.is_private())
-                    .filter_map(|msg: Message| msg.from)
-                    .endpoint(private_handler)
+            .branch(dptree::entry()
+                .filter_command::<ForumCommand>()
+                .filter(|msg: Message, routing: Arc<Routing>| routing.contains(msg.chat.id))
+                .endpoint(forum_command_handler)
             )
             .branch(dptree::entry()
                 .filter_command::<AdminCommand>()
-                .filter(|msg: Message, forum_id: ChatId| msg.chat.id == forum_id)
+                .filter(|msg: Message, routing: Arc<Routing>| routing.contains(msg.chat.id))
                 .filter_map(|msg: Message| msg.thread_id)
                 .endpoint(admin_command_handler)
             )
-            .branch(dptree::filter(|msg: Message, forum_id: ChatId| {
-                msg.chat.id == forum_id && matches!(msg.kind,
+            .branch(dptree::filter(|msg: Message, routing: Arc<Routing>| {
+                routing.contains(msg.chat.id) && matches!(msg.kind,
                     MessageKind::Common(_) | MessageKind::Dice(_)
                 )
             })
@@ -86,46 +169,137 @@ pub fn handler_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync
         )
         .branch(Update::filter_callback_query()
             .branch(dptree::filter(|call: CallbackQuery|
-                call.data.map_or(false, |data| data == "ban")
+                call.data.is_some_and(|data| data == "ban")
             )
                 .filter_map(|call: CallbackQuery|
                     call.message.and_then(|maybe_msg| maybe_msg.regular_message().cloned())
                 )
                 .filter_map(|msg: Message| msg.thread_id)
                 .endpoint(ban_handler))
+            .branch(dptree::filter(|call: CallbackQuery|
+                call.data.is_some_and(|data| data.starts_with(collision::CALLBACK_PREFIX))
+            )
+                .filter_map(|call: CallbackQuery|
+                    call.message.and_then(|maybe_msg| maybe_msg.regular_message().cloned())
+                )
+                .filter(|msg: Message, routing: Arc<Routing>| routing.contains(msg.chat.id))
+                .filter_map(|msg: Message| msg.thread_id)
+                .endpoint(held_message_handler))
+            .branch(dptree::filter(|call: CallbackQuery|
+                call.data.is_some_and(|data| data.starts_with(intake::CALLBACK_PREFIX))
+            )
+                .filter_map(|form: Option<Arc<IntakeForm>>| form)
+                .filter_map(|call: CallbackQuery|
+                    call.message.and_then(|maybe_msg| maybe_msg.regular_message().cloned())
+                )
+                .filter(|msg: Message| msg.chat.is_private())
+                .endpoint(intake_callback_handler))
         )
 }
 
 #[instrument(
     name = "Public command handler",
-    skip(bot, msg, cmd),
+    skip(bot, update, msg, cmd, db, start_texts),
+    fields(update_id = update.id.0, user_id = msg.chat.id.0),
 )]
-async fn public_command_handler(bot: Bot, msg: Message, cmd: PublicCommand) -> HandlerResult {
+async fn public_command_handler(
+    bot: Bot,
+    update: Update,
+    msg: Message,
+    cmd: PublicCommand,
+    db: Database,
+    start_texts: Arc<StartTexts>,
+) -> HandlerResult {
     let response = match cmd {
-        PublicCommand::Start => START_COMMAND.as_str(),
-        PublicCommand::Help => HELP_COMMAND.as_str(),
+        PublicCommand::Start(payload) if msg.chat.is_private() && is_deep_link_payload(&payload) => {
+            db.save_source(msg.chat.id.0, &payload, msg.date.timestamp()).await?;
+            // `START_COMMAND` if there is no text for the payload
+            start_texts.get(&payload).map_or_else(|| START_COMMAND.clone(), str::to_string)
+        },
+        PublicCommand::Start(_) => START_COMMAND.clone(),
+        PublicCommand::Help => HELP_COMMAND.clone(),
     };
     bot.send_message(msg.chat.id, response).await?;
     Ok(())
 }
 
+/// Deep-link payloads are up to 64 characters: `A-Z`, `a-z`, `0-9`, `_` and `-`.
+fn is_deep_link_payload(payload: &str) -> bool {
+    !payload.is_empty()
+        && payload.len() <= 64
+        && payload.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+
+/// Lets the message through if the user is within the rate limit, warns the user on the first
+/// message over it and mutes the user after too many of them. Banned users are never answered.
+async fn rate_limit(bot: Bot, msg: Message, db: Database, limiter: Option<RateLimiter>) -> bool {
+    let Some(limiter) = limiter else {
+        return true;
+    };
+    match check_rate_limit(&bot, &msg, db, &limiter).await {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            // A broken limiter must not stop the support
+            tracing::error!("Failed to check the rate limit: {e:?}");
+            true
+        },
+    }
+}
+
+async fn check_rate_limit(
+    bot: &Bot,
+    msg: &Message,
+    mut db: Database,
+    limiter: &RateLimiter,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let verdict = limiter.check(msg.chat.id.0).await?;
+    if verdict == Verdict::Allowed {
+        return Ok(true);
+    }
+    METRICS.rate_limited.inc();
+    if !matches!(verdict, Verdict::Warned | Verdict::Muted) || db.check_ban(msg.chat.id.0).await? {
+        return Ok(false);
+    }
+    if verdict == Verdict::Warned {
+        bot.send_message(msg.chat.id, "⚠️ You are sending messages too fast, please slow down").await?;
+        return Ok(false);
+    }
+    let mute = utils::format_duration(limiter.limits.mute);
+    bot.send_message(msg.chat.id, format!("🚫 You sent too many messages, they will not be delivered for {mute}"))
+        .await?;
+    if let Some(mapping) = db.get_mapping(msg.chat.id.0).await? {
+        bot.send_message(mapping.forum_id, format!("🚫 The user was muted for {mute} for flooding"))
+            .message_thread_id(ThreadId(MessageId(mapping.recipient_chat.0 as i32)))
+            .await?;
+    }
+    tracing::info!("User muted for flooding: {}", msg.chat.id.0);
+    Ok(false)
+}
+
 #[instrument(
     name = "Private chat handler",
-    skip(bot, msg, user, db, forum_id, scheduler),
+    skip(bot, update, msg, user, db, routing, scheduler, storage, form),
+    fields(update_id = update.id.0, user_id = user.id.0, thread_id = Empty),
 )]
 async fn private_handler(
     bot: Bot,
+    update: Update,
     msg: Message,
     user: User,
     mut db: Database,
-    forum_id: ChatId,
+    routing: Arc<Routing>,
     scheduler: Scheduler,
+    storage: Arc<DialogueStorage>,
+    form: Option<Arc<IntakeForm>>,
 ) -> HandlerResult {
     if db.check_ban(msg.chat.id.0).await? {
         return Ok(());
     }
     if let Some(mut mapping) = db.get_mapping(msg.chat.id.0).await.ok().flatten() {
+        let forum_id = mapping.forum_id;
         let thread_id = ThreadId(MessageId(mapping.recipient_chat.0 as i32));
+        Span::current().record("thread_id", thread_id.0.0);
         let last_topic = if let Some(reply_msg) = msg.reply_to_message() {
             let shift = msg.id.0 - reply_msg.id.0 - 1;
             let reply_msg_id = MessageId(mapping.last_topic.0 - shift);
@@ -138,106 +312,603 @@ async fn private_handler(
                 .message_thread_id(thread_id)
                 .await?
         };
+        METRICS.relayed_to_topic.inc();
+        // Replies are threaded by the last messages, so they are synced before anything can fail
         mapping.sync(msg.id, last_topic);
-        db.sync_mapping(mapping, scheduler).await?;
+        db.sync_mapping(mapping, scheduler.clone()).await?;
+        db.record_message(Event::MessageIn, msg.chat.id.0, msg.date.timestamp(), &scheduler);
+        record_history(&db, &msg, msg.chat.id.0, forum_id.0, thread_id.0.0, last_topic.0, false, &scheduler);
+        db.mark_waiting(msg.chat.id.0, forum_id.0, thread_id.0.0, msg.date.timestamp()).await?;
+        // The card is cosmetic, f.e. it may have been deleted by an admin
+        let profile = card::profile_of(&user, forum_id.0, thread_id.0.0, msg.date.timestamp());
+        match db.update_user(&profile).await {
+            Ok(Some(previous)) => {
+                if let Err(e) = card::refresh(&bot, &db, &previous, &user.first_name).await {
+                    tracing::warn!("Failed to refresh the card: {e}");
+                }
+            },
+            Ok(None) => {},
+            Err(e) => tracing::warn!("Failed to update the user profile: {e}"),
+        }
+    } else if let Some(form) = form {
+        let dialogue = IntakeDialogue::new(storage, msg.chat.id);
+        intake_step(bot, msg, user, db, &routing, &scheduler, &form, dialogue).await?;
     } else {
-        create_new_topic(bot, msg, user, db, forum_id).await?;
+        create_new_topic(bot, &user, FirstMessage::from(&msg), None, db, &routing, &scheduler).await?;
     }
     
     Ok(())
 }
 
+/// Holds the first message of a new user until the intake form is filled.
+async fn intake_step(
+    bot: Bot,
+    msg: Message,
+    user: User,
+    db: Database,
+    routing: &Routing,
+    scheduler: &Scheduler,
+    form: &IntakeForm,
+    dialogue: IntakeDialogue,
+) -> HandlerResult {
+    match dialogue.get().await? {
+        None => {
+            bot.send_message(msg.chat.id, &form.prompt)
+                .reply_markup(form.keyboard())
+                .await?;
+            dialogue.update(IntakeState::Choosing { first_message: FirstMessage::from(&msg) }).await?;
+        },
+        Some(IntakeState::Choosing { .. }) => {
+            bot.send_message(msg.chat.id, "⚠️ Please, choose a category")
+                .reply_markup(form.keyboard())
+                .await?;
+        },
+        Some(IntakeState::Answering { first_message, category, mut answers }) => {
+            let Some(answer) = msg.text() else {
+                bot.send_message(msg.chat.id, "⚠️ Please, answer with a text").await?;
+                return Ok(());
+            };
+            answers.push(answer.to_string());
+            next_question(bot, user, db, routing, scheduler, form, dialogue, first_message, category, answers).await?;
+        },
+    }
+
+    Ok(())
+}
+
+#[instrument(
+    name = "Intake callback handler",
+    skip(bot, update, call, msg, db, routing, scheduler, storage, form),
+    fields(update_id = update.id.0, user_id = call.from.id.0),
+)]
+async fn intake_callback_handler(
+    bot: Bot,
+    update: Update,
+    call: CallbackQuery,
+    msg: Message,
+    db: Database,
+    routing: Arc<Routing>,
+    scheduler: Scheduler,
+    storage: Arc<DialogueStorage>,
+    form: Arc<IntakeForm>,
+) -> HandlerResult {
+    bot.answer_callback_query(call.id.clone()).await?;
+    let dialogue = IntakeDialogue::new(storage, msg.chat.id);
+    // The buttons of an old prompt are ignored
+    let Some(IntakeState::Choosing { first_message }) = dialogue.get().await? else {
+        return Ok(());
+    };
+    let id = call.data.as_deref().unwrap_or_default().trim_start_matches(intake::CALLBACK_PREFIX);
+    let Some(category) = form.category(id) else {
+        return Ok(());
+    };
+    bot.edit_message_text(msg.chat.id, msg.id, format!("{}\n\n✅ {}", form.prompt, html::escape(&category.title)))
+        .await?;
+    let category = category.id.clone();
+    next_question(bot, call.from, db, &routing, &scheduler, &form, dialogue, first_message, category, vec![]).await?;
+
+    Ok(())
+}
+
+/// Asks the next question of the category, or creates the topic if all of them are answered.
+async fn next_question(
+    bot: Bot,
+    user: User,
+    db: Database,
+    routing: &Routing,
+    scheduler: &Scheduler,
+    form: &IntakeForm,
+    dialogue: IntakeDialogue,
+    first_message: FirstMessage,
+    category: String,
+    answers: Vec<String>,
+) -> HandlerResult {
+    // The category may be removed from the form while the user answers
+    let Some(chosen) = form.category(&category) else {
+        dialogue.exit().await?;
+        return create_new_topic(bot, &user, first_message, None, db, routing, scheduler).await;
+    };
+    if let Some(question) = chosen.next_question(&answers) {
+        bot.send_message(dialogue.chat_id(), question).await?;
+        dialogue.update(IntakeState::Answering { first_message, category, answers }).await?;
+        return Ok(());
+    }
+    dialogue.exit().await?;
+    create_new_topic(bot, &user, first_message, Some((chosen, answers)), db, routing, scheduler).await
+}
+
 #[instrument(
     name = "Topic handler",
-    skip(bot, msg, thread_id, db, scheduler),
+    skip(bot, update, msg, thread_id, db, scheduler, lock, signatures, permissions),
+    fields(update_id = update.id.0, thread_id = thread_id.0.0, user_id = Empty),
 )]
 async fn topic_handler(
     bot: Bot,
+    update: Update,
     msg: Message,
     thread_id: ThreadId,
     mut db: Database,
     scheduler: Scheduler,
+    lock: Option<ReplyLock>,
+    signatures: Signatures,
+    permissions: Permissions,
 ) -> HandlerResult {
-    let thread_id = thread_id.0.0;
-    let mut mapping = db.get_mapping(thread_id as i64).await?.ok_or_else(|| {
-        tracing::warn!("Mapping not configured: {thread_id}");
+    let mapping = db.get_topic_mapping(msg.chat.id.0, thread_id.0.0 as i64).await?.ok_or_else(|| {
+        tracing::warn!("Mapping not configured: {}", thread_id.0.0);
         "Mapping not configured"
     })?;
-    let with_reply = msg.reply_to_message()
-        .map_or(false, |reply| reply.id.0 != thread_id);
-    
-    let last_private = if with_reply {
-        let reply_to_message_id = msg.reply_to_message().expect("with reply").id.0;
-        let shift = msg.id.0 - reply_to_message_id - 1;
-        let reply_msg_id = MessageId(mapping.last_private.0 - shift);
-        bot.copy_message(mapping.recipient_chat, msg.chat.id, msg.id)
-            .reply_parameters(ReplyParameters::new(reply_msg_id))
-            .await?
-    } else {
-        bot.copy_message(mapping.recipient_chat, msg.chat.id, msg.id).await?
+    Span::current().record("user_id", mapping.recipient_chat.0);
+    let Some(sender) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    // Observers discuss the topic, their messages are never relayed
+    if permissions.role_of(&bot, &mut db, &msg, sender).await? < Role::Agent {
+        return Ok(());
+    }
+    let reply_to = msg.reply_to_message()
+        .map(|reply| reply.id)
+        .filter(|reply_to| reply_to.0 != thread_id.0.0);
+    if let Some(lock) = lock {
+        // Replies are more important than collisions, so they are relayed if Redis fails
+        let holder = db.lock_reply(msg.chat.id.0, thread_id.0.0, sender.id.0, &sender.full_name(), lock.window)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to lock the topic: {e}");
+                None
+            });
+        if let Some(holder) = holder {
+            let notice = bot.send_message(msg.chat.id, collision::notice_text(&holder, lock.hold))
+                .message_thread_id(thread_id)
+                .reply_parameters(ReplyParameters::new(msg.id));
+            if lock.hold {
+                notice.reply_markup(collision::keyboard(reply_to)).await?;
+                tracing::info!("Message held in {}: {holder} is replying", thread_id.0.0);
+                return Ok(());
+            }
+            notice.await?;
+        }
+    }
+    relay_to_user(&bot, &msg, reply_to, thread_id, mapping, db, scheduler, signatures).await
+}
+
+/// Copies the message of an admin to the user, signed with the name of the admin if it is enabled.
+/// `reply_to` is the message in the topic the admin replies to.
+async fn relay_to_user(
+    bot: &Bot,
+    msg: &Message,
+    reply_to: Option<MessageId>,
+    thread_id: ThreadId,
+    mut mapping: MappingChat,
+    mut db: Database,
+    scheduler: Scheduler,
+    signatures: Signatures,
+) -> HandlerResult {
+    let thread_id = thread_id.0.0;
+    let reply_parameters = reply_to.map(|reply_to| {
+        let shift = msg.id.0 - reply_to.0 - 1;
+        ReplyParameters::new(MessageId(mapping.last_private.0 - shift))
+    });
+    // Anonymous admins post on behalf of the forum and stay anonymous
+    let signer = match msg.from.as_ref().filter(|_| signatures.enabled() && msg.sender_chat.is_none()) {
+        Some(admin) => signatures.signer(db.get_alias(admin.id.0 as i64).await?, admin.full_name()),
+        None => None,
     };
+
+    let last_private = copy_to_user(bot, msg, mapping.recipient_chat, reply_parameters, signer, signatures).await?;
+    METRICS.relayed_to_user.inc();
+    let answered_at = msg.date.timestamp();
+    db.record_message(Event::MessageOut, mapping.recipient_chat.0, answered_at, &scheduler);
+    record_history(&db, msg, mapping.recipient_chat.0, msg.chat.id.0, thread_id, msg.id.0, true, &scheduler);
+    if let Some(since) = db.clear_waiting(mapping.recipient_chat.0).await? {
+        db.record_response_time(answered_at, answered_at - since).await?;
+    }
     mapping.sync(last_private, msg.id);
     db.sync_mapping(mapping, scheduler).await?;
 
     Ok(())
 }
 
+/// Sends or discards a message that was held because another admin is replying.
+/// The notice replies to the held message, so the message comes with the callback,
+/// but without the message it replies to, that one is kept in the callback data.
+#[instrument(
+    name = "Held message handler",
+    skip(bot, update, call, msg, thread_id, db, scheduler, signatures),
+    fields(update_id = update.id.0, thread_id = thread_id.0.0, admin_id = call.from.id.0),
+)]
+async fn held_message_handler(
+    bot: Bot,
+    update: Update,
+    call: CallbackQuery,
+    msg: Message,
+    thread_id: ThreadId,
+    mut db: Database,
+    scheduler: Scheduler,
+    signatures: Signatures,
+) -> HandlerResult {
+    let Some(held) = msg.reply_to_message().filter(|held| held.id.0 != thread_id.0.0) else {
+        bot.answer_callback_query(call.id).await?;
+        return Ok(());
+    };
+    if held.from.as_ref().map(|author| author.id) != Some(call.from.id) {
+        bot.answer_callback_query(call.id)
+            .text("⚠️ Only the author can send the message")
+            .await?;
+        return Ok(());
+    }
+    bot.answer_callback_query(call.id).await?;
+    // Removing the keyboard claims the notice, a repeated press fails here and is ignored
+    if bot.edit_message_reply_markup(msg.chat.id, msg.id)
+        .reply_markup(InlineKeyboardMarkup::default())
+        .await
+        .is_err()
+    {
+        return Ok(());
+    }
+    let choice = call.data.as_deref().and_then(collision::Choice::from_callback);
+    let text = if let Some(collision::Choice::Send { reply_to }) = choice {
+        let Some(mapping) = db.get_topic_mapping(msg.chat.id.0, thread_id.0.0 as i64).await? else {
+            return Ok(());
+        };
+        relay_to_user(&bot, held, reply_to, thread_id, mapping, db, scheduler, signatures).await?;
+        "📨 The message was sent anyway"
+    } else {
+        "🗑 The message was discarded"
+    };
+    bot.edit_message_text(msg.chat.id, msg.id, text).await?;
+
+    Ok(())
+}
+
 #[instrument(
     name = "Admin command handler",
-    skip(bot, msg, thread_id, cmd, forum_id, db, scheduler),
+    skip(bot, update, msg, thread_id, cmd, db, scheduler, permissions),
+    fields(update_id = update.id.0, thread_id = thread_id.0.0),
 )]
 async fn admin_command_handler(
     bot: Bot,
+    update: Update,
     msg: Message,
     thread_id: ThreadId,
-    cmd: AdminCommand,  // while 1 command !!!
-    forum_id: ChatId, 
+    cmd: AdminCommand,
     mut db: Database,
     scheduler: Scheduler,
+    permissions: Permissions,
 ) -> HandlerResult {
-    if let AdminCommand::DropTopic(forum_name) = cmd {
-        if forum_name.is_empty() {
-            bot.send_message(
-                msg.chat.id, 
-                "⚠️ Please, specify a new topic name,\nf.e. /drop_topic {topic_name}"
-            )
+    // Commands work in every forum the users are routed to
+    let forum_id = msg.chat.id;
+    let required = match cmd {
+        AdminCommand::DropTopic(_) => Role::Admin,
+        _ => Role::Agent,
+    };
+    if !authorize(&bot, &mut db, permissions, &msg, required).await? {
+        return Ok(());
+    }
+    match cmd {
+        AdminCommand::DropTopic(forum_name) => {
+            if forum_name.is_empty() {
+                bot.send_message(
+                    msg.chat.id, 
+                    "⚠️ Please, specify a new topic name,\nf.e. /drop_topic {topic_name}"
+                )
+                    .message_thread_id(thread_id).await?;
+                return Ok(());
+            }
+            let thread_id_num = thread_id.0.0 as i64;
+            if let Some(mapping) = db.get_topic_mapping(forum_id.0, thread_id_num).await? {
+                // Delete mapping
+                let _ = db.drop_mapping(forum_id.0, thread_id_num).await;
+                let admin = msg.from.as_ref().ok_or("Command without sender")?;
+                audit_action(&db, &msg, admin, AuditAction::DropTopic, Some(mapping.recipient_chat.0), Some(forum_name.clone())).await?;
+                db.cancel_sync(mapping); // Cancel scheduled synchronization
+                db.clear_waiting(mapping.recipient_chat.0).await?;
+                db.record_event(Event::TopicClosed, msg.date.timestamp(), &scheduler);
+                // Drop topic
+                let forum_name = format!("🗄 {forum_name}");
+                close_topic(&bot, forum_id, thread_id, &forum_name).await?;
+                bot.send_message(msg.chat.id, "🗑 Topic dropped")
+                    .message_thread_id(thread_id).await?;
+                tracing::info!("Topic dropped: {}", thread_id.0.0);
+            }
+        }
+        AdminCommand::Remind(args) => {
+            let Some((delay, text)) = reminders::parse_args(&args) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "⚠️ Please, specify when to remind, up to a year ahead,\nf.e. /remind 2h {text}"
+                )
+                    .message_thread_id(thread_id).await?;
+                return Ok(());
+            };
+            schedule_reminder(&bot, &msg, thread_id, forum_id, db, &scheduler, delay, text, false).await?;
+            bot.send_message(msg.chat.id, format!("⏰ I will remind you in {}", utils::format_duration(delay)))
                 .message_thread_id(thread_id).await?;
-            return Ok(());
         }
-        let thread_id_num = thread_id.0.0 as i64;
-        if let Some(mapping) = db.get_mapping(thread_id_num).await? {
-            // Delete mapping
-            let _ = db.drop_mapping(thread_id_num).await;
-            scheduler.cancel_task(mapping.unique_id() as u64); // Cancel scheduled synchronization
-            // Drop topic
-            let forum_name = format!("🗄 {forum_name}");
-            close_topic(&bot, forum_id, thread_id, &forum_name).await?;
-            bot.send_message(msg.chat.id, "🗑 Topic dropped")
+        AdminCommand::Snooze(args) => {
+            let Some((delay, text)) = reminders::parse_args(&args) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "⚠️ Please, specify for how long to snooze, up to a year,\nf.e. /snooze 1d"
+                )
+                    .message_thread_id(thread_id).await?;
+                return Ok(());
+            };
+            if db.get_topic_mapping(forum_id.0, thread_id.0.0 as i64).await?.is_none() {
+                return Ok(());
+            }
+            schedule_reminder(&bot, &msg, thread_id, forum_id, db, &scheduler, delay, text, true).await?;
+            bot.send_message(msg.chat.id, format!("💤 Topic snoozed for {}", utils::format_duration(delay)))
                 .message_thread_id(thread_id).await?;
-            tracing::info!("Topic dropped: {}", thread_id.0.0);
+            bot.close_forum_topic(forum_id, thread_id).await?;
+            tracing::info!("Topic snoozed: {}", thread_id.0.0);
+        }
+        AdminCommand::Info => {
+            let Some(mapping) = db.get_topic_mapping(forum_id.0, thread_id.0.0 as i64).await? else {
+                return Ok(());
+            };
+            let private_chat = mapping.recipient_chat.0;
+            db.flush_stats().await?;
+            let Some(profile) = db.get_user(private_chat).await? else {
+                bot.send_message(msg.chat.id, "⚠️ The user has not written since the cards were introduced")
+                    .message_thread_id(thread_id).await?;
+                return Ok(());
+            };
+            let bans = db.get_bans(private_chat).await?;
+            let info_msg = bot.send_message(msg.chat.id, card::info_text(&profile, &bans))
+                .message_thread_id(thread_id)
+                .link_preview_options(card::LINK_PREVIEW_OPTIONS)
+                .await?;
+            // Topics created before the cards were introduced get this one pinned
+            if profile.card_message_id.is_none() {
+                bot.pin_chat_message(forum_id, info_msg.id).await?;
+                db.set_card(private_chat, info_msg.id.0).await?;
+            }
+        }
+        AdminCommand::Assign(args) => {
+            let Some(owner) = assign::assignee(&msg).filter(|_| !args.trim().is_empty()) else {
+                bot.send_message(
+                    msg.chat.id,
+                    "⚠️ Please, mention the admin,\nf.e. /assign @alice"
+                )
+                    .message_thread_id(thread_id).await?;
+                return Ok(());
+            };
+            assign_topic(&bot, &msg, thread_id, &mut db, owner).await?;
+        }
+        AdminCommand::Take => {
+            let admin = msg.from.as_ref().ok_or("Command without sender")?;
+            let Some(mapping) = db.get_topic_mapping(forum_id.0, thread_id.0.0 as i64).await? else {
+                return Ok(());
+            };
+            // Only /assign takes the topic away from another admin
+            if let Some(owner) = db.get_owner(mapping.recipient_chat.0).await? {
+                if !assign::is_owner(&owner, admin) {
+                    let text = format!(
+                        "⚠️ The topic is already assigned to {}, use /assign to reassign it",
+                        html::escape(&owner.name),
+                    );
+                    bot.send_message(msg.chat.id, text)
+                        .message_thread_id(thread_id).await?;
+                    return Ok(());
+                }
+            }
+            assign_topic(&bot, &msg, thread_id, &mut db, assign::owner_of(admin)).await?;
         }
     }
     
     Ok(())
 }
 
+#[instrument(
+    name = "Forum command handler",
+    skip(bot, update, msg, cmd, db, permissions),
+    fields(update_id = update.id.0),
+)]
+async fn forum_command_handler(
+    bot: Bot,
+    update: Update,
+    msg: Message,
+    cmd: ForumCommand,
+    mut db: Database,
+    permissions: Permissions,
+) -> HandlerResult {
+    if !authorize(&bot, &mut db, permissions, &msg, cmd.required_role()).await? {
+        return Ok(());
+    }
+    match cmd {
+        ForumCommand::Stats(args) => {
+            let Some(period) = stats::parse_period(&args) else {
+                reply(&bot, &msg, "⚠️ Please, specify the period, up to a year,\nf.e. /stats 30d").await?;
+                return Ok(());
+            };
+            let to = utils::unix_now();
+            // The counters are kept per day, so the current day is included as a whole
+            let from = to - period.as_secs() as i64;
+            let to = to + 24 * 60 * 60;
+            let totals = db.get_totals().await?;
+            let stats = db.get_stats(from, to).await?;
+            let new_topics = db.get_daily_counts(Event::NewUser, from, to).await?;
+            let sources = db.get_source_counts(from, to).await?;
+            reply(&bot, &msg, &stats::stats_text(period, totals, &stats, &new_topics, &sources)).await?;
+        }
+        ForumCommand::Find(query) => {
+            if query.trim().is_empty() {
+                reply(&bot, &msg, "⚠️ Please, specify the id, username or name,\nf.e. /find john").await?;
+                return Ok(());
+            }
+            let users = db.find_users(&query, FIND_LIMIT).await?;
+            if users.is_empty() {
+                reply(&bot, &msg, "🔎 Nobody found").await?;
+                return Ok(());
+            }
+            let mut text = format!("🔎 Found {}:", users.len());
+            for user in users {
+                text.push_str(&format!("\n• {}", user_link(&user)));
+            }
+            reply(&bot, &msg, &text).await?;
+        }
+        ForumCommand::Topic(user_id) => {
+            let Ok(user_id) = user_id.trim().parse::<i64>() else {
+                reply(&bot, &msg, "⚠️ Please, specify the user id,\nf.e. /topic 123456789").await?;
+                return Ok(());
+            };
+            let text = match db.get_topic_user(user_id).await? {
+                Some(user) => format!("💬 {}", user_link(&user)),
+                None => "⚠️ The user has no topic".to_string(),
+            };
+            reply(&bot, &msg, &text).await?;
+        }
+        ForumCommand::Search(args) => {
+            let Some(query) = search::parse_query(&args) else {
+                reply(
+                    &bot,
+                    &msg,
+                    "⚠️ Please, specify what to search,\nf.e. /search refund user:123456789 from:2024-01-01 to:2024-01-31",
+                ).await?;
+                return Ok(());
+            };
+            let messages = db.search_history(&query, SEARCH_LIMIT).await?;
+            reply(&bot, &msg, &search::results_text(&messages)).await?;
+        }
+        ForumCommand::Mine => {
+            let admin = msg.from.as_ref().ok_or("Command without sender")?;
+            let users = db.get_owned_users(admin.id.0 as i64, admin.username.as_deref(), MINE_LIMIT).await?;
+            if users.is_empty() {
+                reply(&bot, &msg, "📭 No topics are assigned to you").await?;
+                return Ok(());
+            }
+            let mut text = format!("📌 Your topics ({}):", users.len());
+            for user in users {
+                text.push_str(&format!("\n• {}", user_link(&user)));
+            }
+            reply(&bot, &msg, &text).await?;
+        }
+        ForumCommand::Alias(args) => {
+            let admin = msg.from.as_ref().ok_or("Command without sender")?;
+            let admin_id = admin.id.0 as i64;
+            let text = match args.trim() {
+                "" => match db.get_alias(admin_id).await? {
+                    Some(alias) => format!("✍️ Your alias is {}", html::escape(&alias)),
+                    None => "✍️ You have no alias, set it with /alias {name}".to_string(),
+                },
+                "off" => {
+                    db.set_alias(admin_id, None).await?;
+                    audit_action(&db, &msg, admin, AuditAction::Alias, None, Some("off".to_string())).await?;
+                    "✍️ Alias removed".to_string()
+                },
+                args => match signature::parse_alias(args) {
+                    Some(alias) => {
+                        db.set_alias(admin_id, Some(&alias)).await?;
+                        audit_action(&db, &msg, admin, AuditAction::Alias, None, Some(alias.clone())).await?;
+                        format!("✍️ Your replies will be signed as {}", html::escape(&alias))
+                    },
+                    None => format!("⚠️ The alias must be up to {} characters", signature::MAX_ALIAS),
+                },
+            };
+            reply(&bot, &msg, &text).await?;
+        }
+        ForumCommand::Role(args) => {
+            let Some((member, role)) = roles::parse_args(&msg, &args) else {
+                reply(
+                    &bot,
+                    &msg,
+                    "⚠️ Please, specify the member and the role: observer, agent, admin, owner or none,\nf.e. /role @alice agent",
+                ).await?;
+                return Ok(());
+            };
+            let Some(member_id) = member.resolve(&db).await? else {
+                let text = format!(
+                    "⚠️ {} has not written in the forum yet, please, specify the id,\nf.e. /role 123456789 agent",
+                    html::escape(&member.name()),
+                );
+                reply(&bot, &msg, &text).await?;
+                return Ok(());
+            };
+            db.set_role(member_id, role).await?;
+            let admin = msg.from.as_ref().ok_or("Command without sender")?;
+            audit_action(&db, &msg, admin, AuditAction::Role, None, Some(args.trim().to_string())).await?;
+            let name = html::escape(&member.name());
+            let text = match role {
+                Some(role) => format!("🎭 {name} is now {}", role.as_str()),
+                None => format!("🎭 The role of {name} is removed"),
+            };
+            reply(&bot, &msg, &text).await?;
+            tracing::info!("Role of {member_id} set to {}", role.map_or("none", |role| role.as_str()));
+        }
+        ForumCommand::Audit(user_id) => {
+            let target = match user_id.trim() {
+                "" => None,
+                user_id => match user_id.parse::<i64>() {
+                    Ok(user_id) => Some(user_id),
+                    Err(_) => {
+                        reply(&bot, &msg, "⚠️ Please, specify the user id,\nf.e. /audit 123456789").await?;
+                        return Ok(());
+                    },
+                },
+            };
+            let entries = db.get_audit(target, Some(AUDIT_LIMIT)).await?;
+            reply(&bot, &msg, &audit::log_text(&entries)).await?;
+        }
+    }
+
+    Ok(())
+}
+
 #[instrument(
     name = "Ban handler",
-    skip(bot, call, msg, thread_id, db, forum_id, scheduler),
+    skip(bot, update, call, msg, thread_id, db, scheduler, permissions),
+    fields(update_id = update.id.0, thread_id = thread_id.0.0, admin_id = call.from.id.0, user_id = Empty),
 )]
 async fn ban_handler(
     bot: Bot, 
+    update: Update,
     call: CallbackQuery,
     msg: Message,
     thread_id: ThreadId,
     mut db: Database, 
-    forum_id: ChatId,
     scheduler: Scheduler,
+    permissions: Permissions,
 ) -> HandlerResult {
-    if let Some(mapping) = db.get_mapping(thread_id.0.0 as i64).await? {
+    // The button is on the card in the user's topic
+    let forum_id = msg.chat.id;
+    if permissions.role_of(&bot, &mut db, &msg, &call.from).await? < Role::Admin {
+        bot.answer_callback_query(call.id)
+            .text("⛔ Only admins can ban users")
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    }
+    if let Some(mapping) = db.get_topic_mapping(forum_id.0, thread_id.0.0 as i64).await? {
+        Span::current().record("user_id", mapping.recipient_chat.0);
         // Ban user
         db.ban_user(mapping.recipient_chat.0).await?;
-        scheduler.cancel_task(mapping.unique_id() as u64); // Cancel scheduled synchronization
+        audit_action(&db, &msg, &call.from, AuditAction::Ban, Some(mapping.recipient_chat.0), None).await?;
+        METRICS.bans.inc();
+        db.cancel_sync(mapping); // Cancel scheduled synchronization
+        db.clear_waiting(mapping.recipient_chat.0).await?;
+        let banned_at = utils::unix_now();
+        db.record_event(Event::Ban, banned_at, &scheduler);
+        db.record_event(Event::TopicClosed, banned_at, &scheduler);
         // Drop topic
         let topic_name = format!("🚫 {}", mapping.recipient_chat);
         close_topic(&bot, forum_id, thread_id, &topic_name).await?;
@@ -258,55 +929,338 @@ async fn ban_handler(
     Ok(())
 }
 
+/// Creates the topic for the user in the routed forum and relays the first message there.
+///
+/// # Arguments
+///
+/// * `intake` - The chosen category of the intake form and the answers to its questions.
 async fn create_new_topic(
     bot: Bot,
-    msg: Message,
-    user: User,
+    user: &User,
+    first_message: FirstMessage,
+    intake: Option<(&intake::Category, Vec<String>)>,
     mut db: Database,
-    forum_id: ChatId,
+    routing: &Routing,
+    scheduler: &Scheduler,
 ) -> HandlerResult {
-    let topic_icon = *TOPIC_ICON_COLOR.choose(&mut thread_rng()).expect("infallible");
-    let topic = bot.create_forum_topic(
+    let private_chat = ChatId(user.id.0 as i64);
+    let source = db.get_source(private_chat.0).await?;
+    let forum_id = routing.forum_for(&Applicant {
+        language_code: user.language_code.as_deref(),
+        source: source.as_deref(),
+        category: intake.as_ref().map(|(category, _)| category.id.as_str()),
+    });
+    let (icon_color, icon_custom_emoji_id) = intake.as_ref()
+        .map(|(category, _)| (category.icon_color, category.icon_custom_emoji_id.clone()))
+        .unwrap_or_default();
+    let topic_icon = icon_color
+        .unwrap_or_else(|| *card::TOPIC_ICON_COLOR.choose(&mut thread_rng()).expect("infallible"));
+    let intake: Option<Intake> = intake.map(|(category, answers)| category.intake(answers));
+    let (forum_id, topic) = create_topic(
+        &bot,
+        &db,
+        routing,
         forum_id,
-        &user.first_name,
+        card::topic_name(&user.first_name, intake.as_ref()),
         topic_icon,
-        "",
+        icon_custom_emoji_id.unwrap_or_default(),
     ).await?;
 
-    let user_info = format!(
-        "<a href=\"{}\"><b>{}</b></a> \
-        \n🆔 <code>{}</code> \
-        \n🎗 Username - {} \
-        \n\n🌐 Language code: {}",
-        user.preferably_tme_url(),
-        user.full_name(),
-        user.id,
-        user.username.as_deref().unwrap_or("None"),
-        user.language_code.as_deref().unwrap_or("None"),
-    );
-    let ban_button = InlineKeyboardMarkup::new(
-        vec![vec![InlineKeyboardButton::callback("🚫 Ban", "ban")]]
-    );
-    let init_msg = bot.send_message(forum_id, user_info)
+    let mut profile = card::profile_of(user, forum_id.0, topic.thread_id.0.0, first_message.date);
+    profile.source = source;
+    profile.intake = intake;
+    let init_msg = bot.send_message(forum_id, card::card_text(&profile))
         .message_thread_id(topic.thread_id)
-        .reply_markup(ban_button)
-        .link_preview_options(LINK_PREVIEW_OPTIONS)
+        .reply_markup(card::ban_button())
+        .link_preview_options(card::LINK_PREVIEW_OPTIONS)
         .await?;
     bot.pin_chat_message(forum_id, init_msg.id).await?;
+    profile.card_message_id = Some(init_msg.id.0);
+    db.save_user(&profile).await?;
 
-    let last_topic = bot.copy_message(forum_id, msg.chat.id, msg.id)
+    let first_message_id = MessageId(first_message.id);
+    let last_topic = bot.copy_message(forum_id, private_chat, first_message_id)
         .message_thread_id(topic.thread_id)
         .await?;
+    METRICS.topics_created.inc();
+    METRICS.relayed_to_topic.inc();
+    db.record_event(Event::NewUser, first_message.date, scheduler);
+    db.record_message(Event::MessageIn, private_chat.0, first_message.date, scheduler);
+    if let Some(text) = first_message.text {
+        db.record_history(HistoryMessage {
+            private_chat: private_chat.0,
+            forum_id: forum_id.0,
+            thread_id: topic.thread_id.0.0,
+            message_id: last_topic.0,
+            outgoing: false,
+            sent_at: first_message.date,
+            text,
+        }, scheduler);
+    }
     
     let topic_chat = ChatId(topic.thread_id.0.0 as i64);
     let mapping = MappingChat::new(
-        msg.chat.id,
+        private_chat,
         topic_chat,
-        msg.id,
+        first_message_id,
         last_topic,
+        forum_id,
     );
     db.save_mapping(mapping).await?;
-    tracing::info!("New topic created: {}", topic_chat.0);
+    db.mark_waiting(private_chat.0, forum_id.0, topic_chat.0 as i32, first_message.date).await?;
+    tracing::info!("New topic created in {}: {}", forum_id.0, topic_chat.0);
+
+    Ok(())
+}
+
+/// Creates the topic in the routed forum or, when it is full, in the first forum of the overflow pool that is not.
+/// The rollover is reported in General of the routed forum.
+///
+/// A forum is full when it has `MAX_OPEN_TOPICS` topics or Telegram refuses to create one more.
+/// The last candidate is always tried, so the error of Telegram is returned if every forum is full.
+async fn create_topic(
+    bot: &Bot,
+    db: &Database,
+    routing: &Routing,
+    forum_id: ChatId,
+    name: String,
+    icon_color: u32,
+    icon_custom_emoji_id: String,
+) -> Result<(ChatId, ForumTopic), Box<dyn std::error::Error + Send + Sync>> {
+    let candidates = routing.candidates(forum_id);
+    let last = candidates.len() - 1;
+    for (i, &candidate) in candidates.iter().enumerate() {
+        if i < last {
+            if let Some(max) = routing.max_open_topics {
+                if db.count_topics(candidate.0).await? >= max {
+                    tracing::warn!("Forum {} has reached MAX_OPEN_TOPICS", candidate.0);
+                    continue;
+                }
+            }
+        }
+        let topic = match bot.create_forum_topic(candidate, name.clone(), icon_color, icon_custom_emoji_id.clone()).await {
+            Ok(topic) => topic,
+            Err(e) if i < last && routing::is_topic_limit(&e) => {
+                tracing::warn!("Forum {} has reached the topic limit: {e}", candidate.0);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if candidate != forum_id {
+            METRICS.topic_rollovers.inc();
+            tracing::info!("Topic rolled over from {} to {}", forum_id.0, candidate.0);
+            let notice = format!(
+                "↪️ This forum is full, the new topic was created in another forum: <a href=\"{}\">{}</a>",
+                utils::topic_link(candidate, topic.thread_id.0.0),
+                html::escape(&topic.name),
+            );
+            // The topic is already created, so a failed notice does not stop the relay
+            if let Err(e) = bot.send_message(forum_id, notice).await {
+                tracing::warn!("Failed to report the rollover to {}: {e}", forum_id.0);
+            }
+        }
+        return Ok((candidate, topic));
+    }
+    unreachable!("the last candidate is always tried")
+}
+
+/// Copies the message, with the signature added to the text or the caption.
+/// Messages that can't be signed are copied as is.
+async fn copy_to_user(
+    bot: &Bot,
+    msg: &Message,
+    private_chat: ChatId,
+    reply_parameters: Option<ReplyParameters>,
+    signer: Option<String>,
+    signatures: Signatures,
+) -> Result<MessageId, teloxide::RequestError> {
+    // The entities of the original message are sent instead of the HTML parse mode
+    let raw = bot.inner();
+    if let Some(name) = signer {
+        if let Some(text) = msg.text() {
+            let entities = msg.entities().unwrap_or_default();
+            if let Some((text, entities)) = signatures.sign(text, entities, &name, signature::MAX_TEXT) {
+                let mut request = raw.send_message(private_chat, text).entities(entities);
+                if let Some(link_preview_options) = msg.link_preview_options() {
+                    request = request.link_preview_options(link_preview_options.clone());
+                }
+                if let Some(reply_parameters) = reply_parameters {
+                    request = request.reply_parameters(reply_parameters);
+                }
+                return Ok(request.await?.id);
+            }
+        } else if signature::has_caption(msg) {
+            let caption = msg.caption().unwrap_or_default();
+            let entities = msg.caption_entities().unwrap_or_default();
+            if let Some((caption, entities)) = signatures.sign(caption, entities, &name, signature::MAX_CAPTION) {
+                let mut request = raw.copy_message(private_chat, msg.chat.id, msg.id)
+                    .caption(caption)
+                    .caption_entities(entities);
+                if let Some(reply_parameters) = reply_parameters {
+                    request = request.reply_parameters(reply_parameters);
+                }
+                return request.await;
+            }
+        }
+    }
+    let mut request = bot.copy_message(private_chat, msg.chat.id, msg.id);
+    if let Some(reply_parameters) = reply_parameters {
+        request = request.reply_parameters(reply_parameters);
+    }
+    request.await
+}
+
+/// Adds the text or the caption of the relayed message to the search index.
+///
+/// # Arguments
+///
+/// * `message_id` - The message in the topic: the copy of an incoming message, or the original outgoing one.
+fn record_history(
+    db: &Database,
+    msg: &Message,
+    private_chat: i64,
+    forum_id: i64,
+    thread_id: i32,
+    message_id: i32,
+    outgoing: bool,
+    scheduler: &Scheduler,
+) {
+    let Some(text) = msg.text().or(msg.caption()) else {
+        return;
+    };
+    db.record_history(HistoryMessage {
+        private_chat,
+        forum_id,
+        thread_id,
+        message_id,
+        outgoing,
+        sent_at: msg.date.timestamp(),
+        text: text.to_string(),
+    }, scheduler);
+}
+
+/// Returns `true` if the sender of the command has the role, otherwise tells the sender.
+async fn authorize(
+    bot: &Bot,
+    db: &mut Database,
+    permissions: Permissions,
+    msg: &Message,
+    required: Role,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let sender = msg.from.as_ref().ok_or("Command without sender")?;
+    let role = permissions.role_of(bot, db, msg, sender).await?;
+    if role >= required {
+        return Ok(true);
+    }
+    let text = format!("⛔ The command needs the {} role, yours is {}", required.as_str(), role.as_str());
+    reply(bot, msg, &text).await?;
+
+    Ok(false)
+}
+
+/// Records the action of the admin in the forum of the message.
+async fn audit_action(
+    db: &Database,
+    msg: &Message,
+    admin: &User,
+    action: AuditAction,
+    target: Option<i64>,
+    args: Option<String>,
+) -> HandlerResult {
+    db.audit(&AuditEntry {
+        actor_id: admin.id.0 as i64,
+        actor_name: admin.full_name(),
+        action,
+        target,
+        forum_id: msg.chat.id.0,
+        thread_id: msg.thread_id.map(|thread_id| thread_id.0.0),
+        args,
+        ts: utils::unix_now(),
+    }).await?;
+
+    Ok(())
+}
+
+/// Answers in the topic of the message, or in General.
+async fn reply(bot: &Bot, msg: &Message, text: &str) -> HandlerResult {
+    let mut request = bot.send_message(msg.chat.id, text)
+        .link_preview_options(card::LINK_PREVIEW_OPTIONS);
+    if let Some(thread_id) = msg.thread_id {
+        request = request.message_thread_id(thread_id);
+    }
+    request.await?;
+
+    Ok(())
+}
+
+/// Formats the user as a link to the topic, with the username and the id.
+fn user_link(user: &UserProfile) -> String {
+    let username = user.username.as_ref()
+        .map(|username| format!(" @{username}"))
+        .unwrap_or_default();
+    format!(
+        "<a href=\"{}\">{}</a>{username} <code>{}</code>",
+        utils::topic_link(ChatId(user.forum_id), user.thread_id),
+        html::escape(&user.full_name),
+        user.chat_id,
+    )
+}
+
+/// Saves the reminder so that it survives restarts, and schedules it.
+async fn schedule_reminder(
+    bot: &Bot,
+    msg: &Message,
+    thread_id: ThreadId,
+    forum_id: ChatId,
+    db: Database,
+    scheduler: &Scheduler,
+    delay: std::time::Duration,
+    text: String,
+    reopen: bool,
+) -> HandlerResult {
+    let admin = msg.from.as_ref().ok_or("Command without sender")?;
+    let mut reminder = Reminder {
+        id: 0,
+        forum_id: forum_id.0,
+        thread_id: thread_id.0.0,
+        admin_id: admin.id.0,
+        admin_name: admin.full_name(),
+        text,
+        due_at: utils::unix_now() + delay.as_secs() as i64,
+        reopen,
+    };
+    reminder.id = db.save_reminder(&reminder).await?;
+    reminders::schedule(scheduler, bot.clone(), db, reminder);
+
+    Ok(())
+}
+
+/// Records the owner of the topic, shows it on the pinned card and mentions the admin in the topic.
+async fn assign_topic(
+    bot: &Bot,
+    msg: &Message,
+    thread_id: ThreadId,
+    db: &mut Database,
+    owner: Owner,
+) -> HandlerResult {
+    let Some(mapping) = db.get_topic_mapping(msg.chat.id.0, thread_id.0.0 as i64).await? else {
+        return Ok(());
+    };
+    let private_chat = mapping.recipient_chat.0;
+    if db.get_owner(private_chat).await?.as_ref() == Some(&owner) {
+        bot.send_message(msg.chat.id, format!("👤 The topic is already assigned to {}", html::escape(&owner.name)))
+            .message_thread_id(thread_id).await?;
+        return Ok(());
+    }
+    db.set_owner(private_chat, &owner).await?;
+    let admin = msg.from.as_ref().ok_or("Command without sender")?;
+    audit_action(db, msg, admin, AuditAction::Assign, Some(private_chat), Some(owner.name.clone())).await?;
+    if let Some(profile) = db.get_user(private_chat).await? {
+        card::edit(bot, &profile).await?;
+    }
+    bot.send_message(msg.chat.id, format!("👤 The topic is assigned to {}", assign::mention(&owner)))
+        .message_thread_id(thread_id).await?;
+    tracing::info!("Topic {} assigned to {}", thread_id.0.0, owner.name);
 
     Ok(())
 }
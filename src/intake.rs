@@ -0,0 +1,173 @@
+use crate::card::TOPIC_ICON_COLOR;
+use crate::db::{DialogueStorage, Intake};
+use crate::errors::ConfigError;
+use serde::{Deserialize, Serialize};
+use teloxide::dispatching::dialogue::Dialogue;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, Message};
+use teloxide::utils::html;
+
+/// Prefix of the callback data of the category buttons.
+pub const CALLBACK_PREFIX: &str = "intake:";
+
+pub type IntakeDialogue = Dialogue<IntakeState, DialogueStorage>;
+
+/// Questions that users answer before their topic is created, loaded from `INTAKE_FORM`.
+#[derive(Deserialize, Debug)]
+pub struct IntakeForm {
+    pub prompt: String,
+    pub categories: Vec<Category>,
+}
+
+/// # Fields
+///
+/// * `id` - Is used in the callback data, so it must be short, and in `FORUM_ROUTES`.
+/// * `icon_color` - Color of the topic icon, a random one by default.
+/// * `icon_custom_emoji_id` - Custom emoji of the topic icon.
+/// * `questions` - Asked one by one, the answers are free text.
+#[derive(Deserialize, Debug)]
+pub struct Category {
+    pub id: String,
+    pub title: String,
+    pub icon_color: Option<u32>,
+    pub icon_custom_emoji_id: Option<String>,
+    #[serde(default)]
+    pub questions: Vec<String>,
+}
+
+/// The message the user started with, it is relayed once the topic is created.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirstMessage {
+    pub id: i32,
+    pub date: i64,
+    pub text: Option<String>,
+}
+
+impl From<&Message> for FirstMessage {
+    fn from(msg: &Message) -> Self {
+        Self {
+            id: msg.id.0,
+            date: msg.date.timestamp(),
+            text: msg.text().or(msg.caption()).map(str::to_string),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IntakeState {
+    Choosing {
+        first_message: FirstMessage,
+    },
+    Answering {
+        first_message: FirstMessage,
+        category: String,
+        answers: Vec<String>,
+    },
+}
+
+impl IntakeForm {
+    /// Reads the form from a JSON file and checks it.
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let json = std::fs::read_to_string(path).map_err(|e| ConfigError::IntakeForm(e.to_string()))?;
+        let form: IntakeForm = serde_json::from_str(&json).map_err(|e| ConfigError::IntakeForm(e.to_string()))?;
+        form.validate().map_err(ConfigError::IntakeForm)?;
+        Ok(form)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.categories.is_empty() {
+            return Err("The intake form has no categories".to_string());
+        }
+        for (i, category) in self.categories.iter().enumerate() {
+            if self.categories[..i].iter().any(|other| other.id == category.id) {
+                return Err(format!("The intake category id is not unique: {}", category.id));
+            }
+            // Callback data is limited to 64 bytes
+            if CALLBACK_PREFIX.len() + category.id.len() > 64 {
+                return Err(format!("The intake category id is too long: {}", category.id));
+            }
+            if category.icon_color.is_some_and(|color| !TOPIC_ICON_COLOR.contains(&color)) {
+                return Err(format!("The topic icon color is not supported by Telegram: {}", category.id));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn category(&self, id: &str) -> Option<&Category> {
+        self.categories.iter().find(|category| category.id == id)
+    }
+
+    pub fn keyboard(&self) -> InlineKeyboardMarkup {
+        InlineKeyboardMarkup::new(self.categories.iter().map(|category| {
+            vec![InlineKeyboardButton::callback(
+                &category.title,
+                format!("{CALLBACK_PREFIX}{}", category.id),
+            )]
+        }))
+    }
+}
+
+impl Category {
+    /// Returns the next question, or `None` if all of them are answered.
+    pub fn next_question(&self, answers: &[String]) -> Option<&str> {
+        self.questions.get(answers.len()).map(String::as_str)
+    }
+
+    /// Pairs the questions with the answers for the user card.
+    pub fn intake(&self, answers: Vec<String>) -> Intake {
+        Intake {
+            category: self.title.clone(),
+            answers: self.questions.iter().cloned().zip(answers).collect(),
+        }
+    }
+}
+
+/// Formats the answers for the user card.
+pub fn intake_text(intake: &Intake) -> String {
+    let mut text = format!("📋 Category: {}", html::escape(&intake.category));
+    for (question, answer) in &intake.answers {
+        text.push_str(&format!("\n❓ {}\n💬 {}", html::escape(question), html::escape(answer)));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form() -> IntakeForm {
+        serde_json::from_str(r#"{
+            "prompt": "What is your question about?",
+            "categories": [
+                {"id": "billing", "title": "💳 Billing", "icon_color": 7322096, "questions": ["Order number?", "What happened?"]},
+                {"id": "other", "title": "Other"}
+            ]
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(form().validate().is_ok());
+        let mut invalid = form();
+        invalid.categories[1].id = "billing".to_string();
+        assert!(invalid.validate().is_err());
+        let mut invalid = form();
+        invalid.categories[1].icon_color = Some(1);
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_questions() {
+        let form = form();
+        let billing = form.category("billing").unwrap();
+        assert_eq!(billing.next_question(&[]), Some("Order number?"));
+        assert_eq!(billing.next_question(&["42".to_string()]), Some("What happened?"));
+        assert_eq!(billing.next_question(&["42".to_string(), "No refund".to_string()]), None);
+        assert_eq!(form.category("other").unwrap().next_question(&[]), None);
+
+        let intake = billing.intake(vec!["42".to_string(), "<No> refund".to_string()]);
+        assert_eq!(
+            intake_text(&intake),
+            "📋 Category: 💳 Billing\n❓ Order number?\n💬 42\n❓ What happened?\n💬 &lt;No&gt; refund",
+        );
+    }
+}
@@ -1,52 +1,174 @@
 use teloxide::adaptors::DefaultParseMode;
 use teloxide::types::{BotCommandScope, ParseMode, Recipient};
-use teloxide::update_listeners::UpdateListener;
 use teloxide::{
     prelude::*,
     update_listeners::webhooks,
 };
-use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::sync::Arc;
 use secrecy::ExposeSecret;
-use handlers::{handler_schema, PublicCommand, AdminCommand};
-use db::{Database, RedisAPI};
-use url::Url;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use handlers::{handler_schema, PublicCommand, AdminCommand, ForumCommand};
+use db::{Database, DialogueStorage, RedisAPI};
+use collision::ReplyLock;
+use intake::IntakeForm;
+use ratelimit::RateLimiter;
+use roles::Permissions;
+use signature::Signatures;
+use routing::Routing;
+use metrics::METRICS;
+use server::{probe_router, ProbeState};
+use sla::SlaPolicy;
 pub use config::Settings;
-pub use scheduler::Scheduler;
+pub use scheduler::{CompletionReport, Namespace, RetryPolicy, Scheduler, TaskInfo};
+pub use telemetry::{init_tracing, TelemetryGuard};
 use teloxide::utils::command::BotCommands;
 
+mod assign;
+mod audit;
+mod card;
+mod collision;
 mod errors;
 mod config;
 mod handlers;
+mod intake;
 mod scheduler;
 mod db;
+mod digest;
+mod metrics;
+mod ratelimit;
+mod reminders;
+mod roles;
+mod routing;
+mod search;
+mod server;
+mod signature;
+mod sla;
+mod stats;
+mod telemetry;
+mod utils;
 
 type Bot = DefaultParseMode<teloxide::Bot>;
 
-pub async fn run_bot(settings: Settings, scheduler: Scheduler) -> Result<(), Box<dyn std::error::Error>> {
+/// Writes the audit log to `out` as JSON lines, newest first.
+/// Returns the number of exported entries.
+///
+/// # Arguments
+///
+/// * `target` - Only the actions about the user.
+pub async fn export_audit(
+    settings: &Settings,
+    target: Option<i64>,
+    out: &mut impl std::io::Write,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let redis_cache = RedisAPI::new(&settings.redis_url, 1800).await?;
+    let db = Database::new(&settings.sqlite_path, redis_cache, settings.sync_batch_size).await?;
+    let entries = db.get_audit(target, None).await?;
+    for entry in &entries {
+        writeln!(out, "{}", audit::to_json(entry))?;
+    }
+    Ok(entries.len())
+}
+
+/// Runs the bot until the update listener stops or `shutdown` is cancelled.
+/// On shutdown the dispatcher stops receiving updates and waits for the handlers in progress.
+pub async fn run_bot(
+    settings: Settings,
+    scheduler: Scheduler,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
     tracing::info!("Starting the bot...");
     // Configure Database
     let redis_cache = RedisAPI::new(&settings.redis_url, 1800).await?;
-    let db = Database::new(&settings.sqlite_path, redis_cache).await?;
+    let dialogue_storage = DialogueStorage::new(redis_cache.clone());
+    let rate_limiter = RateLimiter::from_settings(&settings, redis_cache.clone());
+    let mut db = Database::new(&settings.sqlite_path, redis_cache, settings.sync_batch_size).await?;
+    db.assign_default_forum(settings.forum_id.0).await?;
+    let replayed = db.replay_outbox().await?;
+    if replayed > 0 {
+        tracing::info!("Replayed {replayed} mapping syncs left by the previous run");
+    }
     // Configure bot
     let bot = teloxide::Bot::new(settings.bot_token.expose_secret())
         .parse_mode(ParseMode::Html);
-    let _ = set_bot_commands(&bot, settings.forum_id).await;
+    let routing = Arc::new(Routing::from_settings(&settings));
+    let _ = set_bot_commands(&bot, &routing.forums()).await;
+    let restored = reminders::restore(&scheduler, &bot, &db).await?;
+    if restored > 0 {
+        tracing::info!("Restored {restored} reminders");
+    }
     
+    let intake_form = settings.intake_form.as_deref()
+        .map(IntakeForm::load)
+        .transpose()?
+        .map(Arc::new);
+    let reply_lock = ReplyLock::from_settings(&settings);
+    let signatures = Signatures::from_settings(&settings);
+    let permissions = Permissions::from_settings(&settings);
+    let start_texts = Arc::new(settings.start_texts.clone());
+
     // Handler tree
-    let dependencies = dptree::deps![db, settings.forum_id, scheduler];
+    let dependencies = dptree::deps![
+        db.clone(),
+        scheduler.clone(),
+        dialogue_storage,
+        intake_form,
+        reply_lock,
+        signatures,
+        permissions,
+        rate_limiter,
+        start_texts,
+        routing.clone()
+    ];
     let mut dp = Dispatcher::builder(bot.clone(), handler_schema())
         .dependencies(dependencies)
+        .error_handler(Arc::new(|e| async move {
+            METRICS.handler_errors.inc();
+            tracing::error!("An error from the handler: {e:?}");
+        }))
         .build();
+    if let Some(policy) = SlaPolicy::from_settings(&settings) {
+        let interval = std::time::Duration::from_secs(settings.sla_check_interval);
+        tokio::spawn(sla::watch(bot.clone(), db.clone(), settings.forum_id, policy, interval, shutdown.clone()));
+    }
+    if let Some(period) = settings.digest_period {
+        let thread_id = digest::digest_thread(settings.digest_thread);
+        tokio::spawn(digest::watch(bot.clone(), db.clone(), routing.forums(), period, thread_id, shutdown.clone()));
+    }
+    let probes = probe_router(ProbeState { bot: bot.clone(), db, scheduler });
+    let shutdown_token = dp.shutdown_token();
+    let drain_signal = shutdown.clone();
+    tokio::spawn(async move {
+        drain_signal.cancelled().await;
+        match shutdown_token.shutdown() {
+            Ok(stopped) => {
+                stopped.await;
+                tracing::info!("Dispatcher stopped, all handlers finished");
+            },
+            Err(_) => tracing::warn!("Dispatcher is not running yet, nothing to drain"),
+        }
+    });
     
+    // Probes are internal, so they are never served on the public webhook listener
+    if let Some(http_listener) = settings.http_listener {
+        tracing::info!("Probes listening on: {http_listener}");
+        let tcp_listener = TcpListener::bind(http_listener).await?;
+        tokio::spawn(server::serve(tcp_listener, probes, shutdown.cancelled_owned()));
+    }
     // Webhook or long-polling
     if let Some(webhook_url) = settings.webhook_url {
         let webhook_listener = settings.webhook_listener.expect("settings validated");
 
         tracing::info!("Using webhook: {webhook_url}");
         tracing::info!("Listening on: {webhook_listener}");
+        let tcp_listener = TcpListener::bind(webhook_listener).await?;
+        let options = webhooks::Options::new(webhook_listener, webhook_url);
+        let (listener, stop_flag, router) = webhooks::axum_to_router(bot, options)
+            .await
+            .expect("Couldn't setup webhook");
+        tokio::spawn(server::serve(tcp_listener, router, stop_flag));
         dp.dispatch_with_listener(
-            setup_listener(bot, webhook_url, webhook_listener).await,
+            listener,
             LoggingErrorHandler::with_custom_text("An error from the update listener, try again or use polling"),
         ).await;
     } else {
@@ -57,20 +179,17 @@ pub async fn run_bot(settings: Settings, scheduler: Scheduler) -> Result<(), Box
     Ok(())
 }
 
-async fn setup_listener(bot: Bot, webhook_url: Url, webhook_listener: SocketAddr) -> impl UpdateListener<Err = Infallible> {
-    let options = webhooks::Options::new(webhook_listener, webhook_url);
-    webhooks::axum(bot, options)
-        .await
-        .expect("Couldn't setup webhook")
-}
-
-async fn set_bot_commands(bot: &Bot, forum_id: ChatId) -> Result<(), Box<dyn std::error::Error>> {
+async fn set_bot_commands(bot: &Bot, forums: &[ChatId]) -> Result<(), Box<dyn std::error::Error>> {
     bot.set_my_commands(PublicCommand::bot_commands())
         .scope(BotCommandScope::AllPrivateChats)
         .await?;
-    bot.set_my_commands(AdminCommand::bot_commands())
-        .scope(BotCommandScope::Chat { chat_id: Recipient::Id(forum_id) })
-        .await?;
+    let mut admin_commands = AdminCommand::bot_commands();
+    admin_commands.extend(ForumCommand::bot_commands());
+    for &forum_id in forums {
+        bot.set_my_commands(admin_commands.clone())
+            .scope(BotCommandScope::Chat { chat_id: Recipient::Id(forum_id) })
+            .await?;
+    }
 
     Ok(())
 }
@@ -1,12 +1,22 @@
-use panopticonbot::{run_bot, Settings, Scheduler};
+use panopticonbot::{export_audit, init_tracing, run_bot, Settings, Scheduler};
+use std::time::Duration;
 use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
 async fn main() {
+    let settings = Settings::from_env(".env").expect("Failed to load configuration");
+    // `panopticonbot export-audit [user_id]` prints the audit log as JSON lines and exits
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("export-audit") {
+        let target = args.next().map(|user_id| user_id.parse().expect("The user id must be a number"));
+        export_audit(&settings, target, &mut std::io::stdout().lock())
+            .await
+            .expect("Failed to export the audit log");
+        return;
+    }
     // Logging
-    tracing::subscriber::set_global_default(
-        tracing_subscriber::FmtSubscriber::new()
-    ).expect("Failed to set logger");
+    let _telemetry = init_tracing(&settings).expect("Failed to set logger");
     
     // For graceful shutdown
     let mut terminate = signal(SignalKind::terminate()).expect("Failed to register signal");
@@ -21,20 +31,38 @@ async fn main() {
             _ = quit.recv() => {}
         }
     };
-    let settings = Settings::from_env(".env").expect("Failed to load configuration");
-    let scheduler = Scheduler::new(std::time::Duration::from_secs(60));
-    let bot = async {
-        if let Err(e) = run_bot(settings, scheduler.clone()).await {
-            tracing::error!("{:?}", e);
-        }
+    let shutdown_timeout = Duration::from_secs(settings.shutdown_timeout);
+    let shutdown = CancellationToken::new();
+    let scheduler = Scheduler::new(Duration::from_secs(settings.sync_interval));
+    let bot = run_bot(settings, scheduler.clone(), shutdown.clone());
+    tokio::pin!(bot);
+    let result = tokio::select! {
+        result = &mut bot => Some(result),
+        _ = signal => {
+            tracing::info!("Graceful shutdown...");
+            // Stop receiving updates and let the handlers in progress finish
+            shutdown.cancel();
+            tokio::time::timeout(shutdown_timeout, &mut bot).await.ok().or_else(|| {
+                tracing::warn!("Handlers did not finish in {shutdown_timeout:?}, shutting down anyway");
+                None
+            })
+        },
     };
-    tokio::select! {
-        _ = bot => graceful_shutdown(scheduler).await,
-        _ = signal => graceful_shutdown(scheduler).await,
+    if let Some(Err(e)) = result {
+        tracing::error!("{:?}", e);
     }
+    graceful_shutdown(scheduler, shutdown_timeout).await;
 }
 
-async fn graceful_shutdown(mut scheduler: Scheduler) {
-    println!("\nGraceful shutdown... ");
-    scheduler.complete_all().await;
+/// Flushes scheduled synchronizations, the ones that did not complete in time are dropped.
+async fn graceful_shutdown(mut scheduler: Scheduler, timeout: Duration) {
+    let report = scheduler.complete_all_with_timeout(timeout).await;
+    tracing::info!(
+        flushed = report.completed,
+        failed = report.failed,
+        panicked = report.panicked,
+        dropped = report.dropped,
+        "Scheduler flushed: {} tasks completed, {} failed, {} panicked, {} dropped",
+        report.completed, report.failed, report.panicked, report.dropped,
+    );
 }
@@ -0,0 +1,150 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Process-wide counters exposed on the `/metrics` endpoint.
+pub static METRICS: Metrics = Metrics::new();
+
+/// A monotonically increasing counter.
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Count and sum of observed values, exposed as a Prometheus summary without quantiles.
+pub struct Summary {
+    count: AtomicU64,
+    sum: AtomicU64,  // f64 bits
+}
+
+impl Summary {
+    const fn new() -> Self {
+        Self { count: AtomicU64::new(0), sum: AtomicU64::new(0) }
+    }
+
+    pub fn observe(&self, value: f64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self.sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + value).to_bits())
+        });
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.sum.load(Ordering::Relaxed))
+    }
+}
+
+/// Counters collected by the handlers.
+/// Gauges that are owned by other components (e.g. `Scheduler`)
+/// are passed to `render` at scrape time.
+pub struct Metrics {
+    pub relayed_to_topic: Counter,
+    pub relayed_to_user: Counter,
+    pub topics_created: Counter,
+    pub topic_rollovers: Counter,
+    pub bans: Counter,
+    pub rate_limited: Counter,
+    pub handler_errors: Counter,
+    pub scheduler_task_panics: Counter,
+    pub scheduler_task_retries: Counter,
+    pub scheduler_task_failures: Counter,
+    pub sync_flush_duration: Summary,
+    pub sync_flush_batch_size: Summary,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            relayed_to_topic: Counter::new(),
+            relayed_to_user: Counter::new(),
+            topics_created: Counter::new(),
+            topic_rollovers: Counter::new(),
+            bans: Counter::new(),
+            rate_limited: Counter::new(),
+            handler_errors: Counter::new(),
+            scheduler_task_panics: Counter::new(),
+            scheduler_task_retries: Counter::new(),
+            scheduler_task_failures: Counter::new(),
+            sync_flush_duration: Summary::new(),
+            sync_flush_batch_size: Summary::new(),
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending_tasks` - The number of tasks currently waiting in the `Scheduler`.
+    pub fn render(&self, pending_tasks: usize) -> String {
+        let mut out = String::new();
+        write_header(&mut out, "panopticon_messages_relayed_total", "counter", "Messages relayed between users and topics");
+        let _ = writeln!(out, "panopticon_messages_relayed_total{{direction=\"to_topic\"}} {}", self.relayed_to_topic.get());
+        let _ = writeln!(out, "panopticon_messages_relayed_total{{direction=\"to_user\"}} {}", self.relayed_to_user.get());
+        write_metric(&mut out, "panopticon_topics_created_total", "counter", "Forum topics created", self.topics_created.get());
+        write_metric(&mut out, "panopticon_topic_rollovers_total", "counter", "Topics created in an overflow forum because the routed one was full", self.topic_rollovers.get());
+        write_metric(&mut out, "panopticon_bans_total", "counter", "Users banned", self.bans.get());
+        write_metric(&mut out, "panopticon_rate_limited_total", "counter", "Messages of users dropped by the rate limit", self.rate_limited.get());
+        write_metric(&mut out, "panopticon_handler_errors_total", "counter", "Errors returned by update handlers", self.handler_errors.get());
+        write_metric(&mut out, "panopticon_scheduler_task_panics_total", "counter", "Scheduled tasks that panicked", self.scheduler_task_panics.get());
+        write_metric(&mut out, "panopticon_scheduler_task_retries_total", "counter", "Failed attempts of scheduled tasks that were retried", self.scheduler_task_retries.get());
+        write_metric(&mut out, "panopticon_scheduler_task_failures_total", "counter", "Scheduled tasks that failed after all retries", self.scheduler_task_failures.get());
+        write_metric(&mut out, "panopticon_scheduler_pending_tasks", "gauge", "Tasks waiting in the scheduler", pending_tasks as u64);
+        write_summary(&mut out, "panopticon_sync_flush_duration_seconds", "Time spent writing a batch of mapping syncs", &self.sync_flush_duration);
+        write_summary(&mut out, "panopticon_sync_flush_batch_size", "Mappings written in one batch", &self.sync_flush_batch_size);
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    write_header(out, name, kind, help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn write_summary(out: &mut String, name: &str, help: &str, summary: &Summary) {
+    write_header(out, name, "summary", help);
+    let _ = writeln!(out, "{name}_sum {}", summary.sum());
+    let _ = writeln!(out, "{name}_count {}", summary.count());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.relayed_to_topic.inc();
+        metrics.relayed_to_topic.inc();
+        metrics.bans.inc();
+        metrics.sync_flush_batch_size.observe(3.0);
+        metrics.sync_flush_batch_size.observe(5.0);
+
+        let rendered = metrics.render(7);
+        assert!(rendered.contains("panopticon_messages_relayed_total{direction=\"to_topic\"} 2\n"));
+        assert!(rendered.contains("panopticon_messages_relayed_total{direction=\"to_user\"} 0\n"));
+        assert!(rendered.contains("panopticon_bans_total 1\n"));
+        assert!(rendered.contains("# TYPE panopticon_scheduler_pending_tasks gauge\n"));
+        assert!(rendered.contains("panopticon_scheduler_pending_tasks 7\n"));
+        assert!(rendered.contains("panopticon_sync_flush_batch_size_sum 8\n"));
+        assert!(rendered.contains("panopticon_sync_flush_batch_size_count 2\n"));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::config::{RateLimitStore, Settings};
+use crate::db::RedisAPI;
+use crate::errors;
+use crate::utils::parse_duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Buckets of the chats that are quiet are dropped from memory when there are more of them.
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+/// What happens with a message of the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// The first message over the limit, the user is warned.
+    Warned,
+    /// The next messages over the limit are dropped silently.
+    Dropped,
+    /// Too many messages over the limit, the user is muted from now on.
+    Muted,
+    /// The user is still muted.
+    Silenced,
+}
+
+impl Verdict {
+    /// Reads the verdict returned by `RedisAPI::take_token`.
+    fn from_code(code: u8) -> Self {
+        match code {
+            0 => Verdict::Allowed,
+            1 => Verdict::Warned,
+            2 => Verdict::Dropped,
+            3 => Verdict::Muted,
+            _ => Verdict::Silenced,
+        }
+    }
+}
+
+/// A token bucket of a private chat, the times are unix timestamps in milliseconds.
+/// `violations` are the messages over the limit since the bucket was last full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Bucket {
+    tokens: f64,
+    updated_at: i64,
+    violations: u32,
+    muted_until: i64,
+}
+
+impl Bucket {
+    fn full(limits: &Limits, now: i64) -> Self {
+        Self { tokens: limits.burst as f64, updated_at: now, violations: 0, muted_until: 0 }
+    }
+
+    /// The same logic runs in Redis as `TAKE_TOKEN_SCRIPT`, keep them in sync.
+    fn take(&mut self, limits: &Limits, now: i64) -> Verdict {
+        if now < self.muted_until {
+            return Verdict::Silenced;
+        }
+        let refilled = (now - self.updated_at).max(0) as f64 / limits.refill.as_millis() as f64;
+        self.tokens = (self.tokens + refilled).min(limits.burst as f64);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            // The user slowed down long enough
+            if self.tokens >= limits.burst as f64 {
+                self.violations = 0;
+            }
+            self.tokens -= 1.0;
+            return Verdict::Allowed;
+        }
+        self.violations += 1;
+        if self.violations >= limits.violations {
+            *self = Self { muted_until: now + limits.mute.as_millis() as i64, ..Self::full(limits, now) };
+            Verdict::Muted
+        } else if self.violations == 1 {
+            Verdict::Warned
+        } else {
+            Verdict::Dropped
+        }
+    }
+}
+
+/// # Fields
+///
+/// * `burst` - Messages that can be sent at once.
+/// * `refill` - Time after which one more message can be sent.
+/// * `violations` - Messages over the limit after which the user is muted.
+/// * `mute` - For how long the messages of a muted user are dropped.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub burst: u32,
+    pub refill: Duration,
+    pub violations: u32,
+    pub mute: Duration,
+}
+
+#[derive(Clone)]
+enum Store {
+    Memory(Arc<Mutex<HashMap<i64, Bucket>>>),
+    /// Shared between the instances of the bot.
+    Redis(RedisAPI),
+}
+
+/// Limits how fast users can send messages.
+#[derive(Clone)]
+pub struct RateLimiter {
+    pub limits: Limits,
+    store: Store,
+}
+
+impl RateLimiter {
+    /// Returns `None` if the burst is not configured.
+    pub fn from_settings(settings: &Settings, redis_cache: RedisAPI) -> Option<Self> {
+        let limits = Limits {
+            burst: settings.rate_limit_burst?,
+            refill: parse_duration(&settings.rate_limit_refill)?,
+            violations: settings.rate_limit_violations,
+            mute: parse_duration(&settings.rate_limit_mute)?,
+        };
+        Some(Self::new(limits, settings.rate_limit_store, redis_cache))
+    }
+
+    fn new(limits: Limits, store: RateLimitStore, redis_cache: RedisAPI) -> Self {
+        let store = match store {
+            RateLimitStore::Memory => Store::Memory(Arc::default()),
+            RateLimitStore::Redis => Store::Redis(redis_cache),
+        };
+        Self { limits, store }
+    }
+
+    /// Takes a token from the bucket of the private chat.
+    pub async fn check(&self, private_chat: i64) -> errors::Result<Verdict> {
+        let now = now_millis();
+        match &self.store {
+            Store::Memory(buckets) => {
+                let mut buckets = buckets.lock().expect("poisoned");
+                if buckets.len() > MAX_MEMORY_BUCKETS {
+                    // Muted users are kept until the mute ends, the others start with a full bucket
+                    buckets.retain(|_, bucket| bucket.muted_until > now);
+                }
+                let bucket = buckets.entry(private_chat).or_insert_with(|| Bucket::full(&self.limits, now));
+                Ok(bucket.take(&self.limits, now))
+            },
+            Store::Redis(redis_cache) => {
+                let verdict = redis_cache.clone().take_token(
+                    private_chat,
+                    now,
+                    self.limits.burst,
+                    self.limits.refill.as_millis() as u64,
+                    self.limits.violations,
+                    self.limits.mute.as_millis() as u64,
+                ).await?;
+                Ok(Verdict::from_code(verdict))
+            },
+        }
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::get_test_redis;
+
+    const LIMITS: Limits = Limits {
+        burst: 2,
+        refill: Duration::from_secs(10),
+        violations: 3,
+        mute: Duration::from_secs(60),
+    };
+
+    #[test]
+    fn test_bucket() {
+        let mut bucket = Bucket::full(&LIMITS, 0);
+        assert_eq!(bucket.take(&LIMITS, 0), Verdict::Allowed);
+        assert_eq!(bucket.take(&LIMITS, 1_000), Verdict::Allowed);
+        assert_eq!(bucket.take(&LIMITS, 2_000), Verdict::Warned);
+        assert_eq!(bucket.take(&LIMITS, 3_000), Verdict::Dropped);
+        // A token is refilled, but the violations are kept until the bucket is full
+        assert_eq!(bucket.take(&LIMITS, 10_000), Verdict::Allowed);
+        assert_eq!(bucket.take(&LIMITS, 11_000), Verdict::Muted);
+        assert_eq!(bucket.take(&LIMITS, 70_000), Verdict::Silenced);
+        assert_eq!(bucket.take(&LIMITS, 71_000), Verdict::Allowed);
+
+        let mut bucket = Bucket::full(&LIMITS, 0);
+        bucket.take(&LIMITS, 0);
+        bucket.take(&LIMITS, 0);
+        assert_eq!(bucket.take(&LIMITS, 0), Verdict::Warned);
+        // The bucket is full again, so the user starts over
+        bucket.take(&LIMITS, 30_000);
+        bucket.take(&LIMITS, 30_000);
+        assert_eq!(bucket.take(&LIMITS, 30_000), Verdict::Warned);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let limiter = RateLimiter { limits: LIMITS, store: Store::Memory(Arc::default()) };
+        assert_eq!(limiter.check(1).await.unwrap(), Verdict::Allowed);
+        assert_eq!(limiter.check(1).await.unwrap(), Verdict::Allowed);
+        assert_eq!(limiter.check(1).await.unwrap(), Verdict::Warned);
+        // Every private chat has its own bucket
+        assert_eq!(limiter.check(2).await.unwrap(), Verdict::Allowed);
+    }
+
+    #[tokio::test]
+    async fn test_redis_store() {
+        let limiter = RateLimiter { limits: LIMITS, store: Store::Redis(get_test_redis().await) };
+        // Concurrent messages of one user take the tokens one by one
+        let verdicts = tokio::join!(limiter.check(70), limiter.check(70), limiter.check(70), limiter.check(70));
+        let verdicts = [verdicts.0, verdicts.1, verdicts.2, verdicts.3];
+        let allowed = verdicts.iter().filter(|verdict| matches!(verdict, Ok(Verdict::Allowed))).count();
+        assert_eq!(allowed, 2);
+        assert_eq!(limiter.check(71).await.unwrap(), Verdict::Allowed);
+    }
+}
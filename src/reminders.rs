@@ -0,0 +1,97 @@
+use crate::db::{Database, Reminder};
+use crate::errors;
+use crate::scheduler::{Namespace, Scheduler};
+use crate::Bot;
+use crate::utils::parse_duration;
+use std::time::{Duration, UNIX_EPOCH};
+use teloxide::prelude::*;
+use teloxide::{ApiError, RequestError};
+use teloxide::types::{MessageId, ThreadId};
+use teloxide::utils::html;
+
+/// Reminders are persisted in SQLite and scheduled again on startup,
+/// so they are not executed on shutdown.
+pub const REMINDERS: Namespace = Namespace::new("reminders", false);
+
+/// Reminders are limited to a year ahead.
+const MAX_DELAY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Splits command arguments into the delay and the optional text,
+/// f.e. `2h check refund`.
+pub fn parse_args(args: &str) -> Option<(Duration, String)> {
+    let args = args.trim();
+    let (delay, text) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let delay = parse_duration(delay).filter(|delay| *delay <= MAX_DELAY)?;
+    Some((delay, text.trim().to_string()))
+}
+
+/// Schedules the reminder to be posted into its topic.
+pub fn schedule(scheduler: &Scheduler, bot: Bot, db: Database, reminder: Reminder) {
+    let deadline = UNIX_EPOCH + Duration::from_secs(reminder.due_at.max(0) as u64);
+    scheduler.add_task_at(REMINDERS, reminder.id as u64, deadline, move || async move {
+        let id = reminder.id;
+        if let Err(e) = fire(bot, db, reminder).await {
+            tracing::error!("Failed to send reminder {id}: {e}");
+        }
+    });
+}
+
+/// Schedules all reminders saved by the previous runs.
+/// Overdue ones are posted right away.
+pub async fn restore(scheduler: &Scheduler, bot: &Bot, db: &Database) -> errors::Result<usize> {
+    let reminders = db.get_reminders().await?;
+    let count = reminders.len();
+    for reminder in reminders {
+        schedule(scheduler, bot.clone(), db.clone(), reminder);
+    }
+    Ok(count)
+}
+
+/// The reminder is deleted only once it is sent, a failed one is sent again on the next startup.
+async fn fire(bot: Bot, mut db: Database, reminder: Reminder) -> errors::Result<()> {
+    // The topic may have been dropped or the user banned in the meantime
+    if db.get_topic_mapping(reminder.forum_id, reminder.thread_id as i64).await?.is_none() {
+        db.delete_reminder(reminder.id).await?;
+        return Ok(());
+    }
+    let forum_id = ChatId(reminder.forum_id);
+    let thread_id = ThreadId(MessageId(reminder.thread_id));
+    if reminder.reopen {
+        match bot.reopen_forum_topic(forum_id, thread_id).await {
+            // Reopened by an admin before the snooze ended
+            Err(RequestError::Api(ApiError::Unknown(e))) if e.contains("TOPIC_NOT_MODIFIED") => {},
+            result => { result?; },
+        }
+    }
+    let mention = html::user_mention(UserId(reminder.admin_id), &html::escape(&reminder.admin_name));
+    let text = if reminder.text.is_empty() {
+        format!("⏰ {mention}, you asked to remind you about this topic")
+    } else {
+        format!("⏰ {mention}, reminder: {}", html::escape(&reminder.text))
+    };
+    bot.send_message(forum_id, text)
+        .message_thread_id(thread_id)
+        .await?;
+    db.delete_reminder(reminder.id).await?;
+    tracing::info!("Reminder sent: {}", reminder.id);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args("2h check refund"),
+            Some((Duration::from_secs(2 * 60 * 60), "check refund".to_string())),
+        );
+        assert_eq!(parse_args(" 15m "), Some((Duration::from_secs(15 * 60), String::new())));
+        assert_eq!(parse_args("soon check refund"), None);
+        assert_eq!(parse_args(""), None);
+        assert_eq!(parse_args("366d"), None);
+        assert_eq!(parse_args("9223372036000000000s"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::config::Settings;
+use crate::db::Database;
+use crate::errors;
+use crate::Bot;
+use serde::Deserialize;
+use teloxide::prelude::*;
+use teloxide::types::{ChatMemberKind, MessageEntityKind, User};
+
+/// What a member of the forum may do, each role can do everything the previous one can.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Reads the topics, the messages are never relayed.
+    Observer,
+    /// Replies to users and runs the commands of a topic.
+    Agent,
+    /// Also bans users and drops topics.
+    Admin,
+    /// Also manages the roles.
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Observer => "observer",
+            Role::Agent => "agent",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Role::Observer, Role::Agent, Role::Admin, Role::Owner]
+            .into_iter()
+            .find(|role| role.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// The role of a member without a role set with `/role`.
+    fn of_status(kind: &ChatMemberKind, default_role: Role) -> Self {
+        match kind {
+            ChatMemberKind::Owner(_) => Role::Owner,
+            ChatMemberKind::Administrator(_) => Role::Admin,
+            _ => default_role,
+        }
+    }
+}
+
+/// The role of the members who are neither set with `/role` nor admins of the forum.
+#[derive(Clone, Copy, Debug)]
+pub struct Permissions {
+    pub default_role: Role,
+}
+
+impl Permissions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self { default_role: settings.default_role }
+    }
+
+    /// Returns the role set with `/role`, or the one that follows from the status in the forum.
+    /// Anonymous admins post on behalf of the forum, so they are admins.
+    pub async fn role_of(&self, bot: &Bot, db: &mut Database, msg: &Message, member: &User) -> errors::Result<Role> {
+        if msg.sender_chat.as_ref().is_some_and(|chat| chat.id == msg.chat.id) {
+            return Ok(Role::Admin);
+        }
+        let member_id = member.id.0 as i64;
+        if let Some(role) = db.get_role(member_id).await? {
+            return Ok(role);
+        }
+        if let Some(role) = db.get_cached_role(msg.chat.id.0, member_id).await? {
+            return Ok(role);
+        }
+        let status = bot.get_chat_member(msg.chat.id, member.id).await?;
+        let role = Role::of_status(&status.kind, self.default_role);
+        // The username is refreshed as often as the cached role
+        db.save_member(member_id, member.username.as_deref()).await?;
+        db.cache_role(msg.chat.id.0, member_id, role).await?;
+        Ok(role)
+    }
+}
+
+/// Who `/role` is about: the id is known for the mentions of members without a username
+/// and when the id is given, `@username` mentions are resolved to the id.
+/// Roles are stored by the id only, so they never pass to whoever takes the username later.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Member {
+    Id { id: i64, username: Option<String> },
+    Username(String),
+}
+
+impl Member {
+    /// Returns the id the role is stored by, `None` if no member with the username wrote in a forum.
+    pub async fn resolve(&self, db: &Database) -> errors::Result<Option<i64>> {
+        match self {
+            Member::Id { id, .. } => Ok(Some(*id)),
+            Member::Username(username) => db.get_member_id(username).await,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Member::Id { username: Some(username), .. } | Member::Username(username) => format!("@{username}"),
+            Member::Id { id, username: None } => id.to_string(),
+        }
+    }
+}
+
+/// Parses `/role @alice agent` or `/role 123456789 none`, `None` removes the role.
+pub fn parse_args(msg: &Message, args: &str) -> Option<(Member, Option<Role>)> {
+    let (target, role) = args.trim().rsplit_once(char::is_whitespace)?;
+    let role = match role {
+        "none" => None,
+        role => Some(Role::from_name(role)?),
+    };
+    let target = target.trim();
+    let member = if let Ok(id) = target.parse::<i64>() {
+        Member::Id { id, username: None }
+    } else {
+        msg.parse_entities()?.into_iter().find_map(|entity| match entity.kind() {
+            MessageEntityKind::TextMention { user } => {
+                Some(Member::Id { id: user.id.0 as i64, username: user.username.clone() })
+            },
+            MessageEntityKind::Mention => Some(Member::Username(entity.text().trim_start_matches('@').to_string())),
+            _ => None,
+        })?
+    };
+    Some((member, role))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles() {
+        assert_eq!(Role::from_name("Agent"), Some(Role::Agent));
+        assert_eq!(Role::from_name("boss"), None);
+        assert!(Role::Owner > Role::Admin && Role::Admin > Role::Agent && Role::Agent > Role::Observer);
+        assert_eq!(Role::of_status(&ChatMemberKind::Member, Role::Observer), Role::Observer);
+
+        assert_eq!(Member::Username("Alice".to_string()).name(), "@Alice");
+        let bob = Member::Id { id: 7, username: Some("bob".to_string()) };
+        assert_eq!(bob.name(), "@bob");
+        let nameless = Member::Id { id: 8, username: None };
+        assert_eq!(nameless.name(), "8");
+    }
+
+    #[test]
+    fn test_forum_commands() {
+        use crate::handlers::ForumCommand;
+
+        let readers = [
+            ForumCommand::Stats("30d".to_string()),
+            ForumCommand::Find("john".to_string()),
+            ForumCommand::Topic("1".to_string()),
+            ForumCommand::Search("refund".to_string()),
+            ForumCommand::Mine,
+            ForumCommand::Alias("Anna".to_string()),
+        ];
+        // An observer is refused every command of the forum
+        for cmd in readers {
+            assert_eq!(cmd.required_role(), Role::Agent);
+            assert!(Role::Observer < cmd.required_role());
+        }
+        assert_eq!(ForumCommand::Audit(String::new()).required_role(), Role::Admin);
+        assert_eq!(ForumCommand::Role(String::new()).required_role(), Role::Owner);
+    }
+}
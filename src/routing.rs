@@ -0,0 +1,206 @@
+use crate::config::Settings;
+use teloxide::{types::ChatId, ApiError, RequestError};
+
+/// What is known about a new user when the topic is created.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Applicant<'a> {
+    pub language_code: Option<&'a str>,
+    pub source: Option<&'a str>,
+    pub category: Option<&'a str>,
+}
+
+/// Matches new users by the language, the `/start` payload or the id of the intake category.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Language(String),
+    Source(String),
+    Category(String),
+}
+
+impl Rule {
+    fn matches(&self, applicant: &Applicant) -> bool {
+        match self {
+            // `en` also matches regional codes like `en-GB`
+            Rule::Language(language) => applicant.language_code.is_some_and(|code| {
+                let code = code.to_ascii_lowercase();
+                code == *language || code.strip_prefix(language.as_str()).is_some_and(|rest| rest.starts_with('-'))
+            }),
+            Rule::Source(source) => applicant.source == Some(source.as_str()),
+            Rule::Category(category) => applicant.category == Some(category.as_str()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub rule: Rule,
+    pub forum_id: ChatId,
+}
+
+/// Forums the topics are created in: the first matching route, or `FORUM_ID`.
+/// When the forum is full, the topic is created in the first forum of the `overflow` pool that is not.
+/// `max_open_topics` is the limit of topics per forum, only the limit of Telegram applies if it is not set.
+#[derive(Clone, Debug)]
+pub struct Routing {
+    pub default_forum: ChatId,
+    pub routes: Vec<Route>,
+    pub overflow: Vec<ChatId>,
+    pub max_open_topics: Option<i64>,
+}
+
+impl Routing {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let routes = settings.forum_routes.as_deref()
+            .and_then(parse_routes)
+            .unwrap_or_default();
+        let overflow = settings.overflow_forums.as_deref()
+            .and_then(parse_forums)
+            .unwrap_or_default();
+        Self {
+            default_forum: settings.forum_id,
+            routes,
+            overflow,
+            max_open_topics: settings.max_open_topics,
+        }
+    }
+
+    pub fn forum_for(&self, applicant: &Applicant) -> ChatId {
+        self.routes.iter()
+            .find(|route| route.rule.matches(applicant))
+            .map_or(self.default_forum, |route| route.forum_id)
+    }
+
+    /// Returns the routed forum and then the overflow pool, without duplicates.
+    pub fn candidates(&self, forum_id: ChatId) -> Vec<ChatId> {
+        let mut forums = vec![forum_id];
+        for &forum in &self.overflow {
+            if !forums.contains(&forum) {
+                forums.push(forum);
+            }
+        }
+        forums
+    }
+
+    /// Returns the default forum, the forums of the routes and the overflow pool, without duplicates.
+    pub fn forums(&self) -> Vec<ChatId> {
+        let mut forums = vec![self.default_forum];
+        let routed = self.routes.iter().map(|route| route.forum_id);
+        for forum in routed.chain(self.overflow.iter().copied()) {
+            if !forums.contains(&forum) {
+                forums.push(forum);
+            }
+        }
+        forums
+    }
+
+    pub fn contains(&self, chat_id: ChatId) -> bool {
+        chat_id == self.default_forum
+            || self.routes.iter().any(|route| route.forum_id == chat_id)
+            || self.overflow.contains(&chat_id)
+    }
+}
+
+/// Parses comma-separated forum ids like `-100123,-100456`.
+pub fn parse_forums(input: &str) -> Option<Vec<ChatId>> {
+    input.split(',')
+        .map(|forum_id| forum_id.trim().parse().ok().map(ChatId))
+        .collect()
+}
+
+/// Returns `true` if Telegram refused to create a topic because the forum has too many of them.
+/// teloxide has no variant for this error, so it is recognized by the description,
+/// f.e. `Bad Request: TOPICS_TOO_MUCH`.
+pub fn is_topic_limit(error: &RequestError) -> bool {
+    matches!(error, RequestError::Api(ApiError::Unknown(description)) if description.contains("TOPICS_TOO_MUCH"))
+}
+
+/// Parses comma-separated routes like `language:ru=-100123,source:ads=-100456,category:billing=-100789`.
+pub fn parse_routes(input: &str) -> Option<Vec<Route>> {
+    input.split(',')
+        .map(|route| {
+            let (rule, forum_id) = route.trim().split_once('=')?;
+            let (kind, value) = rule.split_once(':')?;
+            let value = value.trim().to_string();
+            if value.is_empty() {
+                return None;
+            }
+            let rule = match kind.trim() {
+                "language" => Rule::Language(value.to_ascii_lowercase()),
+                "source" => Rule::Source(value),
+                "category" => Rule::Category(value),
+                _ => return None,
+            };
+            Some(Route { rule, forum_id: ChatId(forum_id.trim().parse().ok()?) })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_routes() {
+        assert_eq!(
+            parse_routes("language:RU=-100, source:ads=-200"),
+            Some(vec![
+                Route { rule: Rule::Language("ru".to_string()), forum_id: ChatId(-100) },
+                Route { rule: Rule::Source("ads".to_string()), forum_id: ChatId(-200) },
+            ]),
+        );
+        assert_eq!(parse_routes("country:ru=-100"), None);
+        assert_eq!(parse_routes("language:ru=sales"), None);
+        assert_eq!(parse_routes("language:=-100"), None);
+        assert_eq!(parse_routes(""), None);
+    }
+
+    #[test]
+    fn test_forum_for() {
+        let routing = Routing {
+            default_forum: ChatId(-1),
+            routes: parse_routes("category:billing=-200,language:ru=-100,source:ads=-200").unwrap(),
+            overflow: Vec::new(),
+            max_open_topics: None,
+        };
+        let applicant = |language_code, source, category| Applicant { language_code, source, category };
+
+        assert_eq!(routing.forum_for(&applicant(Some("ru"), None, None)), ChatId(-100));
+        assert_eq!(routing.forum_for(&applicant(Some("ru-RU"), None, None)), ChatId(-100));
+        assert_eq!(routing.forum_for(&applicant(Some("rue"), None, None)), ChatId(-1));
+        // The first matching route wins
+        assert_eq!(routing.forum_for(&applicant(Some("ru"), None, Some("billing"))), ChatId(-200));
+        assert_eq!(routing.forum_for(&applicant(Some("en"), Some("ads"), None)), ChatId(-200));
+        assert_eq!(routing.forum_for(&Applicant::default()), ChatId(-1));
+        assert_eq!(routing.forums(), vec![ChatId(-1), ChatId(-200), ChatId(-100)]);
+        assert!(routing.contains(ChatId(-100)));
+        assert!(!routing.contains(ChatId(-300)));
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(parse_forums("-300, -400"), Some(vec![ChatId(-300), ChatId(-400)]));
+        assert_eq!(parse_forums("-300,sales"), None);
+
+        let routing = Routing {
+            default_forum: ChatId(-1),
+            routes: parse_routes("language:ru=-100").unwrap(),
+            overflow: vec![ChatId(-300), ChatId(-1)],
+            max_open_topics: Some(900),
+        };
+        assert_eq!(routing.candidates(ChatId(-100)), vec![ChatId(-100), ChatId(-300), ChatId(-1)]);
+        assert_eq!(routing.candidates(ChatId(-1)), vec![ChatId(-1), ChatId(-300)]);
+        assert_eq!(routing.forums(), vec![ChatId(-1), ChatId(-100), ChatId(-300)]);
+        assert!(routing.contains(ChatId(-300)));
+    }
+
+    #[test]
+    fn test_is_topic_limit() {
+        let api_error = |description: &str| RequestError::Api(ApiError::Unknown(description.to_string()));
+
+        assert!(is_topic_limit(&api_error("Bad Request: TOPICS_TOO_MUCH")));
+        assert!(!is_topic_limit(&api_error("Bad Request: CHAT_NOT_MODIFIED")));
+        // Other limits do not mean the forum is full
+        assert!(!is_topic_limit(&api_error("Bad Request: message text is too long, the limit is exceeded")));
+        assert!(!is_topic_limit(&RequestError::Api(ApiError::BotBlocked)));
+    }
+}
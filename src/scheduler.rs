@@ -1,12 +1,123 @@
+use crate::metrics::METRICS;
+use rand::{thread_rng, Rng};
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
 use tokio::select;
-use tokio::time::{Duration, sleep, Instant};
+use tokio::sync::Notify;
+use tokio::time::{Duration, sleep, sleep_until, Instant};
 use tokio_util::sync::CancellationToken;
 
 type TaskId = u64;
-type TaskData = (CancellationToken, Instant);
+type TaskKey = (&'static str, TaskId);
+/// The cancellation token, the timestamp of adding and the deadline of the task.
+type TaskData = (CancellationToken, Instant, Instant);
+
+/// A named group of tasks, task IDs are unique within a namespace.
+///
+/// # Fields
+///
+/// * `name` - The name of the namespace.
+/// * `complete_on_shutdown` - Whether `complete_all` executes the tasks right away.
+///   Otherwise they are cancelled, f.e. when they are persisted and will be added again on startup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Namespace {
+    pub name: &'static str,
+    pub complete_on_shutdown: bool,
+}
+
+impl Namespace {
+    pub const fn new(name: &'static str, complete_on_shutdown: bool) -> Self {
+        Self { name, complete_on_shutdown }
+    }
+}
+
+/// The namespace of tasks added with `add_task` and `add_retrying_task`.
+pub const DEFAULT_NAMESPACE: Namespace = Namespace::new("default", true);
+
+/// A snapshot of a pending task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub namespace: &'static str,
+    pub id: TaskId,
+    /// Time left until the task starts.
+    pub remaining: Duration,
+}
+
+/// Summary of a `Scheduler::complete_all` run.
+///
+/// # Fields
+///
+/// * `completed` - Tasks that were executed to the end.
+/// * `failed` - Tasks that returned an error after all retry attempts.
+/// * `panicked` - Tasks that panicked during execution.
+/// * `dropped` - Tasks that were still pending when the timeout expired.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompletionReport {
+    pub completed: usize,
+    pub failed: usize,
+    pub panicked: usize,
+    pub dropped: usize,
+}
+
+/// Counters of finished tasks, shared by all task wrappers.
+#[derive(Default)]
+struct TaskStats {
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+    panicked: AtomicUsize,
+}
+
+impl TaskStats {
+    fn snapshot(&self) -> CompletionReport {
+        CompletionReport {
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            panicked: self.panicked.load(Ordering::Relaxed),
+            dropped: 0,
+        }
+    }
+}
+
+/// How a fallible task is retried: exponential backoff with jitter.
+///
+/// # Fields
+///
+/// * `max_attempts` - Total number of attempts, including the first one.
+/// * `initial_backoff` - Delay before the second attempt, doubled for every next one.
+/// * `max_backoff` - Upper bound of the delay.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delay after the failed `attempt` (starting from 1).
+    /// A random half of the delay is dropped, so that retries of different tasks do not align.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let backoff = self.initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        let half = backoff / 2;
+        half + half.mul_f64(thread_rng().gen::<f64>())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
 
 /// A `Scheduler` for managing tasks with a configurable timeout.
 /// Tasks are added and can be cancelled or automatically removed after a certain duration.
@@ -14,14 +125,18 @@ type TaskData = (CancellationToken, Instant);
 ///
 /// # Fields
 ///
-/// * `tasks` - A map of task IDs to task data.
-/// * `task_duration` - Time after which the task will start execution.
+/// * `tasks` - A map of namespaced task IDs to task data.
+/// * `task_duration` - Time after which the task will start execution, unless a delay is given.
 /// * `start_token` - A token used to control the startup of all tasks.
+/// * `idle` - Notified every time the task map becomes empty.
+/// * `stats` - Counters of completed, failed and panicked tasks.
 #[derive(Clone)]
 pub struct Scheduler {
-    tasks: Arc<RwLock<HashMap<TaskId, TaskData>>>,
+    tasks: Arc<RwLock<HashMap<TaskKey, TaskData>>>,
     task_duration: Duration,
     start_token: CancellationToken,
+    idle: Arc<Notify>,
+    stats: Arc<TaskStats>,
 }
 
 impl Scheduler {
@@ -35,9 +150,16 @@ impl Scheduler {
             tasks: Arc::new(RwLock::new(HashMap::new())),
             task_duration,
             start_token: CancellationToken::new(),
+            idle: Arc::new(Notify::new()),
+            stats: Arc::new(TaskStats::default()),
         }
     }
 
+    /// Returns the delay of the tasks added with `add_task`.
+    pub fn task_duration(&self) -> Duration {
+        self.task_duration
+    }
+
     /// Adds a new task to the scheduler.
     /// If a task with the same ID already exists, it will be cancelled and replaced.
     ///
@@ -50,7 +172,103 @@ impl Scheduler {
         F: FnOnce() -> Fut + Send + 'static,
         Fut: Future<Output = ()> + Send + 'static,
     {
-        let old_task = self.cancel_task(task_id);
+        self.add_task_after(DEFAULT_NAMESPACE, task_id, self.task_duration, task);
+    }
+
+    /// Adds a new task that starts after `delay`.
+    /// If a task with the same ID already exists in the namespace, it will be cancelled and replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace of the task.
+    /// * `task_id` - The ID of the task.
+    /// * `delay` - Time after which the task will start execution.
+    /// * `task` - A closure that returns a `Future`, representing the task logic.
+    pub fn add_task_after<F, Fut>(&self, namespace: Namespace, task_id: TaskId, delay: Duration, task: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let task = task();
+        self.spawn_task(namespace, task_id, Instant::now() + delay, async move {
+            task.await;
+            true
+        });
+    }
+
+    /// Adds a new task that starts at `deadline`, or right away if it has already passed.
+    /// If a task with the same ID already exists in the namespace, it will be cancelled and replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace of the task.
+    /// * `task_id` - The ID of the task.
+    /// * `deadline` - Wall-clock time at which the task will start execution.
+    /// * `task` - A closure that returns a `Future`, representing the task logic.
+    pub fn add_task_at<F, Fut>(&self, namespace: Namespace, task_id: TaskId, deadline: SystemTime, task: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let delay = deadline.duration_since(SystemTime::now()).unwrap_or_default();
+        self.add_task_after(namespace, task_id, delay, task);
+    }
+
+    /// Adds a new task that is retried according to the `policy` while it returns an error.
+    /// If a task with the same ID already exists, it will be cancelled and replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `task_id` - The ID of the task.
+    /// * `policy` - How many times and how often the task is retried.
+    /// * `task` - A closure that returns a `Future` for every attempt.
+    pub fn add_retrying_task<F, Fut, E>(&self, task_id: TaskId, policy: RetryPolicy, task: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        self.add_retrying_task_in(DEFAULT_NAMESPACE, task_id, policy, task);
+    }
+
+    /// Adds a new retrying task to the namespace, see `add_retrying_task`.
+    /// If a task with the same ID already exists in the namespace, it will be cancelled and replaced.
+    pub fn add_retrying_task_in<F, Fut, E>(&self, namespace: Namespace, task_id: TaskId, policy: RetryPolicy, mut task: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        let deadline = Instant::now() + self.task_duration;
+        self.spawn_task(namespace, task_id, deadline, async move {
+            let mut attempt = 1;
+            loop {
+                match task().await {
+                    Ok(()) => return true,
+                    Err(e) if attempt >= policy.max_attempts => {
+                        tracing::error!("Task {task_id} failed after {attempt} attempts: {e}");
+                        METRICS.scheduler_task_failures.inc();
+                        return false;
+                    },
+                    Err(e) => {
+                        let backoff = policy.backoff(attempt);
+                        tracing::warn!("Task {task_id} failed (attempt {attempt}), retrying in {backoff:?}: {e}");
+                        METRICS.scheduler_task_retries.inc();
+                        sleep(backoff).await;
+                        attempt += 1;
+                    },
+                }
+            }
+        });
+    }
+
+    /// Registers the task and spawns its wrapper.
+    /// The task resolves to `false` if it failed.
+    fn spawn_task<Fut>(&self, namespace: Namespace, task_id: TaskId, deadline: Instant, task: Fut)
+    where
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        let old_task = self.cancel_task_in(namespace, task_id);
         // Create a new cancellation token for this task
         let cancel_token = CancellationToken::new();
         let token_clone = cancel_token.clone();
@@ -58,96 +276,182 @@ impl Scheduler {
         let timestamp = Instant::now();
         {
             let mut tasks = self.tasks.write().unwrap();
-            tasks.insert(task_id, (token_clone, timestamp));
+            tasks.insert((namespace.name, task_id), (token_clone, timestamp, deadline));
         }
         tokio::spawn(Self::task_wrapper(
-            task(),
-            self.task_duration,
-            // Control tokens
+            task,
+            namespace,
+            deadline,
             cancel_token,
-            self.start_token.clone(),
-            // For cleanup
-            self.tasks.clone(),
+            // Holds the start token and the task map for cleanup
+            self.clone(),
             task_id,
             timestamp,
         ));
 
         if old_task {
-            tracing::info!("Task updated: {task_id}");
+            tracing::info!("Task updated: {}:{task_id}", namespace.name);
         } else {
-            tracing::info!("Added task: {task_id}");
+            tracing::info!("Added task: {}:{task_id}", namespace.name);
         }
     }
 
     /// Cancels a task by its ID.
     /// Returns `true` if the task was successfully cancelled, `false` if no such task exists.
     pub fn cancel_task(&self, task_id: TaskId) -> bool {
+        self.cancel_task_in(DEFAULT_NAMESPACE, task_id)
+    }
+
+    /// Cancels a task by its ID in the namespace.
+    /// Returns `true` if the task was successfully cancelled, `false` if no such task exists.
+    pub fn cancel_task_in(&self, namespace: Namespace, task_id: TaskId) -> bool {
         let tasks = self.tasks.read().unwrap();
-        if let Some((token, _)) = tasks.get(&task_id) {
+        if let Some((token, _, _)) = tasks.get(&(namespace.name, task_id)) {
             token.cancel();
             return true;
         }
         false
     }
 
+    /// Returns the pending tasks of the namespace, the ones that start first go first.
+    pub fn tasks(&self, namespace: Namespace) -> Vec<TaskInfo> {
+        let now = Instant::now();
+        let mut tasks: Vec<TaskInfo> = self.tasks.read().unwrap()
+            .iter()
+            .filter(|((name, _), _)| *name == namespace.name)
+            .map(|((name, id), (_, _, deadline))| TaskInfo {
+                namespace: name,
+                id: *id,
+                remaining: deadline.saturating_duration_since(now),
+            })
+            .collect();
+        tasks.sort_by_key(|task| task.remaining);
+        tasks
+    }
+
+    /// Returns the number of tasks that are waiting to be executed.
+    pub fn pending_tasks(&self) -> usize {
+        self.tasks.read().unwrap().len()
+    }
+
+    /// Returns the number of tasks that panicked since the scheduler was created.
+    pub fn panicked_tasks(&self) -> usize {
+        self.stats.panicked.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of tasks that failed after all retry attempts.
+    pub fn failed_tasks(&self) -> usize {
+        self.stats.failed.load(Ordering::Relaxed)
+    }
+
     /// Completes all tasks, canceling the shared start_token and replacing it with a new one.
+    /// Tasks of namespaces that are not completed on shutdown are cancelled.
     /// Waits until all tasks are either completed or canceled.
-    pub async fn complete_all(&mut self) {
+    pub async fn complete_all(&mut self) -> CompletionReport {
+        let before = self.start_all();
+        self.wait_idle().await;
+        self.report(before)
+    }
+
+    /// Same as `complete_all`, but gives up after `timeout`.
+    /// Tasks that are still pending by then are reported as dropped.
+    pub async fn complete_all_with_timeout(&mut self, timeout: Duration) -> CompletionReport {
+        let before = self.start_all();
+        if tokio::time::timeout(timeout, self.wait_idle()).await.is_err() {
+            tracing::warn!("Tasks did not complete in {timeout:?}");
+        }
+        self.report(before)
+    }
+
+    /// Gives all tasks a signal to start executing.
+    /// Returns the counters at that moment to build a report later.
+    fn start_all(&mut self) -> CompletionReport {
+        let counters = self.stats.snapshot();
         // Canceling the start token gives all tasks a signal to start executing
         self.start_token.cancel();
         self.start_token = CancellationToken::new();
         tracing::info!("Completion of all tasks...");
+        counters
+    }
 
-        // Wait until the task map is empty, indicating all tasks have finished
+    /// Waits until the task map is empty, indicating all tasks have finished.
+    async fn wait_idle(&self) {
         loop {
-            { // Additional scope to not take away map access permanently
-                let tasks = self.tasks.read().unwrap();
-                if tasks.is_empty() { break; }
-            }
-            sleep(Duration::from_millis(100)).await;
+            // Subscribe before checking the map, so that the notification is not missed
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.tasks.read().unwrap().is_empty() { break; }
+            notified.await;
+        }
+    }
+
+    fn report(&self, before: CompletionReport) -> CompletionReport {
+        let now = self.stats.snapshot();
+        CompletionReport {
+            completed: now.completed - before.completed,
+            failed: now.failed - before.failed,
+            panicked: now.panicked - before.panicked,
+            dropped: self.pending_tasks(),
         }
     }
 
     /// Internal function to wrap task execution logic.
-    /// Handles cancellation and ensures task cleanup after execution.
+    /// Handles cancellation and ensures task cleanup after execution, even if the task panics.
     ///
     /// # Arguments
     ///
-    /// * `task` - The task to execute.
-    /// * `task_duration` - The duration after which the task is forcefully completed.
+    /// * `task` - The task to execute, resolves to `false` if it failed.
+    /// * `namespace` - The namespace of the task.
+    /// * `deadline` - The moment the task starts execution.
     /// * `cancel_token` - Token to cancel this specific task.
-    /// * `start_token` - The token to start this task.
-    /// * `tasks` - Shared reference to the task map.
+    /// * `scheduler` - The scheduler with the start token and the task map.
     /// * `task_id` - The ID of the task.
     /// * `task_timestamp` - The timestamp of when the task was added.
     async fn task_wrapper<F>(
         task: F,
-        task_duration: Duration,
+        namespace: Namespace,
+        deadline: Instant,
         cancel_token: CancellationToken,
-        start_token: CancellationToken,
-        tasks: Arc<RwLock<HashMap<TaskId, TaskData>>>,
+        scheduler: Scheduler,
         task_id: TaskId,
         task_timestamp: Instant,
     )
     where
-        F: Future<Output = ()> + Send + 'static,
+        F: Future<Output = bool> + Send + 'static,
     {
-        select! {
-            _ = cancel_token.cancelled() => {},
-            _ = start_token.cancelled() => { task.await; },
-            _ = sleep(task_duration) => { task.await; },
+        let run = select! {
+            _ = cancel_token.cancelled() => false,
+            _ = scheduler.start_token.cancelled() => namespace.complete_on_shutdown,
+            _ = sleep_until(deadline) => true,
+        };
+        if run {
+            // A separate tokio task catches a panic, so the cleanup below still happens
+            match tokio::spawn(task).await {
+                Ok(true) => { scheduler.stats.completed.fetch_add(1, Ordering::Relaxed); },
+                Ok(false) => { scheduler.stats.failed.fetch_add(1, Ordering::Relaxed); },
+                Err(e) => {
+                    scheduler.stats.panicked.fetch_add(1, Ordering::Relaxed);
+                    METRICS.scheduler_task_panics.inc();
+                    tracing::error!("Task {}:{task_id} panicked: {e}", namespace.name);
+                },
+            }
         }
         
         // Task is required to delete its id after completion
-        let mut tasks = tasks.write().unwrap();
-        if let Some((_, timestamp)) = tasks.get(&task_id) {
+        let mut tasks = scheduler.tasks.write().unwrap();
+        let key = (namespace.name, task_id);
+        if let Some((_, timestamp, _)) = tasks.get(&key) {
             // When adding a task, the old task should not cancel the new task,
             // the old task may not have time to complete
             // before the cancel_token is replaced with the new one
             if *timestamp == task_timestamp {
-                tasks.remove(&task_id);
+                tasks.remove(&key);
             }
         }
+        if tasks.is_empty() {
+            scheduler.idle.notify_waiters();
+        }
     }
 }
 
@@ -175,11 +479,11 @@ mod tests {
         let scheduler = Scheduler::new(Duration::from_secs(2));
         let task_id = 1;
 
-        assert_eq!(scheduler.cancel_task(task_id), false);
+        assert!(!scheduler.cancel_task(task_id));
         scheduler.add_task(task_id, || async {
             /* Something to do */
         });
-        assert_eq!(scheduler.cancel_task(task_id), true);
+        assert!(scheduler.cancel_task(task_id));
     }
 
     #[tokio::test]
@@ -214,9 +518,127 @@ mod tests {
         }
         scheduler.complete_all().await;
         for task_id in task_ids {
-            assert_eq!(scheduler.cancel_task(task_id), false);
+            assert!(!scheduler.cancel_task(task_id));
         }
         let final_count = *counter.read().unwrap();
         assert_eq!(final_count, 3);
     }
+
+    #[tokio::test]
+    async fn test_complete_all_with_timeout() {
+        let mut scheduler = Scheduler::new(Duration::from_secs(10));
+        scheduler.add_task(1, || async {});
+        scheduler.add_task(2, || async {
+            sleep(Duration::from_secs(5)).await;
+        });
+
+        let report = scheduler.complete_all_with_timeout(Duration::from_secs(1)).await;
+        assert_eq!(report, CompletionReport { completed: 1, failed: 0, panicked: 0, dropped: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_panicked_task() {
+        let mut scheduler = Scheduler::new(Duration::from_secs(10));
+        scheduler.add_task(1, || async {
+            panic!("Task failed");
+        });
+
+        let report = scheduler.complete_all_with_timeout(Duration::from_secs(1)).await;
+        assert_eq!(report, CompletionReport { completed: 0, failed: 0, panicked: 1, dropped: 0 });
+        assert_eq!(scheduler.panicked_tasks(), 1);
+        assert_eq!(scheduler.pending_tasks(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_retrying_task() {
+        let mut scheduler = Scheduler::new(Duration::from_secs(10));
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+        };
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let attempts_clone = Arc::clone(&attempts);
+        scheduler.add_retrying_task(1, policy, move || {
+            let attempts = Arc::clone(&attempts_clone);
+            async move {
+                // Succeeds on the second attempt
+                match attempts.fetch_add(1, Ordering::Relaxed) {
+                    0 => Err("database is locked"),
+                    _ => Ok(()),
+                }
+            }
+        });
+        scheduler.add_retrying_task(2, policy, || async { Err("database is locked") });
+
+        let report = scheduler.complete_all_with_timeout(Duration::from_secs(1)).await;
+        assert_eq!(report, CompletionReport { completed: 1, failed: 1, panicked: 0, dropped: 0 });
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        assert_eq!(scheduler.failed_tasks(), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(8),
+        };
+        for (attempt, max) in [(1, 1), (2, 2), (3, 4), (4, 8), (9, 8)] {
+            let backoff = policy.backoff(attempt);
+            assert!(backoff >= Duration::from_secs(max) / 2);
+            assert!(backoff <= Duration::from_secs(max));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_namespaces() {
+        const REMINDERS: Namespace = Namespace::new("reminders", false);
+        let scheduler = Scheduler::new(Duration::from_secs(10));
+
+        scheduler.add_task(1, || async {});
+        scheduler.add_task_after(REMINDERS, 1, Duration::from_secs(20), || async {});
+        scheduler.add_task_at(REMINDERS, 2, SystemTime::now() + Duration::from_secs(5), || async {});
+
+        let tasks = scheduler.tasks(REMINDERS);
+        assert_eq!(tasks.iter().map(|task| task.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert!(tasks[0].remaining <= Duration::from_secs(5));
+        assert_eq!(scheduler.tasks(DEFAULT_NAMESPACE).len(), 1);
+        assert_eq!(scheduler.pending_tasks(), 3);
+        // The same ID in another namespace is not affected
+        assert!(scheduler.cancel_task_in(REMINDERS, 1));
+        assert!(scheduler.cancel_task(1));
+    }
+
+    #[tokio::test]
+    async fn test_add_task_after() {
+        const FOLLOW_UPS: Namespace = Namespace::new("follow_ups", true);
+        let scheduler = Scheduler::new(Duration::from_secs(10));
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let counter_clone = Arc::clone(&counter);
+        scheduler.add_task_after(FOLLOW_UPS, 1, Duration::from_millis(100), move || async move {
+            counter_clone.fetch_add(1, Ordering::Relaxed);
+        });
+        sleep(Duration::from_millis(300)).await;
+        assert_eq!(counter.load(Ordering::Relaxed), 1);
+        assert!(scheduler.tasks(FOLLOW_UPS).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_complete_all_skips_persistent_namespace() {
+        const REMINDERS: Namespace = Namespace::new("reminders", false);
+        let mut scheduler = Scheduler::new(Duration::from_secs(10));
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let counter_clone = Arc::clone(&counter);
+        scheduler.add_task_after(REMINDERS, 1, Duration::from_secs(60), move || async move {
+            counter_clone.fetch_add(1, Ordering::Relaxed);
+        });
+        let report = scheduler.complete_all_with_timeout(Duration::from_secs(1)).await;
+        assert_eq!(report, CompletionReport::default());
+        assert_eq!(counter.load(Ordering::Relaxed), 0);
+        assert_eq!(scheduler.pending_tasks(), 0);
+    }
 }
@@ -0,0 +1,93 @@
+use crate::db::{HistoryMessage, SearchQuery};
+use crate::utils::{format_date, message_link};
+use chrono::NaiveDate;
+use teloxide::types::ChatId;
+use teloxide::utils::html;
+
+/// Parses `/search` arguments: the terms and the optional
+/// `user:<id>`, `from:<YYYY-MM-DD>` and `to:<YYYY-MM-DD>` filters, both dates are inclusive.
+pub fn parse_query(args: &str) -> Option<SearchQuery> {
+    let mut query = SearchQuery::default();
+    for word in args.split_whitespace() {
+        match word.split_once(':') {
+            Some(("user", id)) => query.private_chat = Some(id.parse().ok()?),
+            Some(("from", date)) => query.from = Some(parse_date(date)?),
+            Some(("to", date)) => query.to = Some(parse_date(date)? + 24 * 60 * 60),
+            _ => query.terms.push(word.to_string()),
+        }
+    }
+    if query.terms.is_empty() {
+        return None;
+    }
+    Some(query)
+}
+
+/// Returns the unix timestamp of the start of the day.
+fn parse_date(date: &str) -> Option<i64> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp())
+}
+
+/// Formats the found messages with links to them in the topics.
+pub fn results_text(messages: &[HistoryMessage]) -> String {
+    if messages.is_empty() {
+        return "🔎 Nothing found".to_string();
+    }
+    let mut text = format!("🔎 Found {}:", messages.len());
+    for message in messages {
+        let direction = if message.outgoing { "📤" } else { "📥" };
+        // The snippet marks the matched terms with control characters, see `Database::search_history`
+        let snippet = html::escape(&message.text)
+            .replace('\u{2}', "<b>")
+            .replace('\u{3}', "</b>");
+        text.push_str(&format!(
+            "\n\n{direction} <a href=\"{}\">{}</a> <code>{}</code>\n{snippet}",
+            message_link(ChatId(message.forum_id), message.thread_id, message.message_id),
+            format_date(message.sent_at),
+            message.private_chat,
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query("refund user:42 from:2024-01-03 to:2024-01-03 late"),
+            Some(SearchQuery {
+                terms: vec!["refund".to_string(), "late".to_string()],
+                private_chat: Some(42),
+                from: Some(1_704_240_000),
+                to: Some(1_704_326_400),
+            }),
+        );
+        assert_eq!(parse_query("user:42"), None);
+        assert_eq!(parse_query("refund user:john"), None);
+        assert_eq!(parse_query("refund from:yesterday"), None);
+        assert_eq!(parse_query(""), None);
+    }
+
+    #[test]
+    fn test_results_text() {
+        let messages = [HistoryMessage {
+            private_chat: 1,
+            forum_id: -100123456789,
+            thread_id: 42,
+            message_id: 100,
+            outgoing: false,
+            sent_at: 1_704_283_200,
+            text: "Where is my <\u{2}refund\u{3}>?".to_string(),
+        }];
+
+        assert_eq!(
+            results_text(&messages),
+            "🔎 Found 1:\n\n📥 <a href=\"https://t.me/c/123456789/42/100\">2024-01-03 12:00 UTC</a> <code>1</code>\
+            \nWhere is my &lt;<b>refund</b>&gt;?",
+        );
+        assert_eq!(results_text(&[]), "🔎 Nothing found");
+    }
+}
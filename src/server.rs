@@ -0,0 +1,75 @@
+use crate::db::Database;
+use crate::metrics::METRICS;
+use crate::scheduler::Scheduler;
+use crate::Bot;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use std::future::Future;
+use teloxide::prelude::*;
+use tokio::net::TcpListener;
+
+/// Everything the probes need to check the bot dependencies.
+#[derive(Clone)]
+pub struct ProbeState {
+    pub bot: Bot,
+    pub db: Database,
+    pub scheduler: Scheduler,
+}
+
+/// Routes for liveness, readiness and Prometheus metrics.
+/// They are internal, so they are served on their own listener and never next to the webhook.
+pub fn probe_router(state: ProbeState) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+/// Serves the router until `shutdown` resolves.
+/// The listener is bound by the caller, so a busy port fails the startup instead of this task.
+pub async fn serve<F>(tcp_listener: TcpListener, router: Router, shutdown: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    if let Err(e) = axum::serve(tcp_listener, router)
+        .with_graceful_shutdown(shutdown)
+        .await
+    {
+        tracing::error!("HTTP server error: {e}");
+    }
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(State(mut state): State<ProbeState>) -> impl IntoResponse {
+    let mut failed = Vec::new();
+    if let Err(e) = state.db.ping_sqlite().await {
+        failed.push(format!("sqlite: {e}"));
+    }
+    if let Err(e) = state.db.ping_redis().await {
+        failed.push(format!("redis: {e}"));
+    }
+    if let Err(e) = state.bot.get_me().await {
+        failed.push(format!("telegram: {e}"));
+    }
+
+    if failed.is_empty() {
+        (StatusCode::OK, "ready".to_string())
+    } else {
+        tracing::warn!("Readiness check failed: {failed:?}");
+        (StatusCode::SERVICE_UNAVAILABLE, failed.join("\n"))
+    }
+}
+
+async fn metrics(State(state): State<ProbeState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(state.scheduler.pending_tasks()),
+    )
+}
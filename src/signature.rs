@@ -0,0 +1,129 @@
+use crate::config::{Settings, SignatureMode, SignaturePosition};
+use teloxide::types::{Message, MessageEntity};
+
+/// Texts are limited to 4096 characters, counted in UTF-16 code units like the entities.
+pub const MAX_TEXT: usize = 4096;
+/// Captions are limited to 1024 characters, counted the same way.
+pub const MAX_CAPTION: usize = 1024;
+/// Aliases are limited to 32 characters.
+pub const MAX_ALIAS: usize = 32;
+
+/// Whether the replies of admins are signed and where the name goes.
+#[derive(Clone, Copy, Debug)]
+pub struct Signatures {
+    pub mode: SignatureMode,
+    pub position: SignaturePosition,
+}
+
+impl Signatures {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self { mode: settings.signature_mode, position: settings.signature_position }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !matches!(self.mode, SignatureMode::Off)
+    }
+
+    /// Returns the name the admin signs with, or `None` if the replies of the admin are not signed.
+    /// In the opt-in mode only the admins with an alias sign.
+    pub fn signer(&self, alias: Option<String>, full_name: String) -> Option<String> {
+        match self.mode {
+            SignatureMode::Off => None,
+            SignatureMode::Always => Some(alias.unwrap_or(full_name)),
+            SignatureMode::OptIn => alias,
+        }
+    }
+
+    /// Adds the name to the text and shifts the entities after it.
+    /// Returns `None` if the signed text would be longer than `limit`, then the text is relayed as is.
+    pub fn sign(
+        &self,
+        text: &str,
+        entities: &[MessageEntity],
+        name: &str,
+        limit: usize,
+    ) -> Option<(String, Vec<MessageEntity>)> {
+        let mut entities = entities.to_vec();
+        let signed = match self.position {
+            SignaturePosition::Prefix => {
+                let prefix = format!("{name}:\n");
+                let shift = utf16_len(&prefix);
+                for entity in &mut entities {
+                    entity.offset += shift;
+                }
+                entities.insert(0, MessageEntity::bold(0, utf16_len(name) + 1));
+                format!("{prefix}{text}")
+            },
+            SignaturePosition::Suffix => {
+                let suffix = format!("— {name}");
+                entities.push(MessageEntity::italic(utf16_len(text) + 2, utf16_len(&suffix)));
+                format!("{text}\n\n{suffix}")
+            },
+        };
+        if utf16_len(&signed) > limit {
+            tracing::warn!("The reply of {name} is not signed, the signed text would exceed {limit} characters");
+            return None;
+        }
+        Some((signed, entities))
+    }
+}
+
+/// Returns `true` for the messages that can have a caption.
+pub fn has_caption(msg: &Message) -> bool {
+    msg.photo().is_some()
+        || msg.video().is_some()
+        || msg.animation().is_some()
+        || msg.audio().is_some()
+        || msg.document().is_some()
+        || msg.voice().is_some()
+}
+
+/// Normalizes the argument of `/alias`, `None` if it is not a valid alias.
+pub fn parse_alias(input: &str) -> Option<String> {
+    let alias = input.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!alias.is_empty() && alias.chars().count() <= MAX_ALIAS).then_some(alias)
+}
+
+/// Entity offsets are counted in UTF-16 code units.
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        let suffix = Signatures { mode: SignatureMode::Always, position: SignaturePosition::Suffix };
+        let prefix = Signatures { position: SignaturePosition::Prefix, ..suffix };
+        let entities = [MessageEntity::code(3, 4)];
+
+        let (text, signed_entities) = suffix.sign("🙂 Try again", &entities, "Alice", MAX_TEXT).unwrap();
+        assert_eq!(text, "🙂 Try again\n\n— Alice");
+        assert_eq!(signed_entities, vec![MessageEntity::code(3, 4), MessageEntity::italic(14, 7)]);
+        let (text, signed_entities) = prefix.sign("🙂 Try again", &entities, "Alice", MAX_TEXT).unwrap();
+        assert_eq!(text, "Alice:\n🙂 Try again");
+        assert_eq!(signed_entities, vec![MessageEntity::bold(0, 6), MessageEntity::code(10, 4)]);
+        assert!(suffix.sign(&"a".repeat(MAX_CAPTION), &[], "Alice", MAX_CAPTION).is_none());
+        // An emoji takes two UTF-16 code units
+        assert!(suffix.sign(&"🙂".repeat(MAX_CAPTION / 2 - 2), &[], "Alice", MAX_CAPTION).is_none());
+        assert!(suffix.sign(&"a".repeat(MAX_CAPTION - 10), &[], "Alice", MAX_CAPTION).is_some());
+    }
+
+    #[test]
+    fn test_signer() {
+        let mut signatures = Signatures { mode: SignatureMode::OptIn, position: SignaturePosition::Suffix };
+        assert_eq!(signatures.signer(Some("Support".to_string()), "Alice".to_string()).as_deref(), Some("Support"));
+        assert_eq!(signatures.signer(None, "Alice".to_string()), None);
+        signatures.mode = SignatureMode::Always;
+        assert_eq!(signatures.signer(None, "Alice".to_string()).as_deref(), Some("Alice"));
+        signatures.mode = SignatureMode::Off;
+        assert_eq!(signatures.signer(Some("Support".to_string()), "Alice".to_string()), None);
+        assert!(!signatures.enabled());
+
+        assert_eq!(parse_alias("  Anna   from support "), Some("Anna from support".to_string()));
+        assert_eq!(parse_alias(" "), None);
+        assert_eq!(parse_alias(&"a".repeat(MAX_ALIAS + 1)), None);
+    }
+}
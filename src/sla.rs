@@ -0,0 +1,178 @@
+use crate::config::Settings;
+use crate::db::{Database, Waiting};
+use crate::errors;
+use crate::utils::{format_duration, parse_duration, topic_link, unix_now};
+use crate::Bot;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{MessageId, ThreadId};
+use teloxide::utils::html;
+use tokio_util::sync::CancellationToken;
+
+/// When and where to alert about users that wait for a reply.
+///
+/// # Fields
+///
+/// * `thresholds` - Waiting times of the escalation steps, in ascending order.
+/// * `alerts_thread` - Topic of the default forum for the alerts about its users.
+///   The alerts about users of the other forums, or all of them if it is not set, are posted into the user's topic.
+/// * `mentions` - On-duty admins, mentioned on the last escalation step.
+pub struct SlaPolicy {
+    pub thresholds: Vec<Duration>,
+    pub alerts_thread: Option<ThreadId>,
+    pub mentions: Option<String>,
+}
+
+impl SlaPolicy {
+    /// Returns `None` if the thresholds are not configured.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        let thresholds = parse_thresholds(settings.sla_thresholds.as_deref()?)?;
+        Some(Self {
+            thresholds,
+            alerts_thread: settings.sla_alerts_thread.map(|id| ThreadId(MessageId(id))),
+            mentions: settings.sla_mentions.clone(),
+        })
+    }
+
+    /// Returns the number of thresholds the waiting time has passed.
+    pub fn escalation(&self, waited: Duration) -> u32 {
+        self.thresholds.iter().filter(|&&threshold| waited >= threshold).count() as u32
+    }
+
+    fn is_last(&self, escalation: u32) -> bool {
+        escalation as usize == self.thresholds.len()
+    }
+
+    /// Returns the chat and the topic for the alert, `forum_id` is the default forum.
+    fn alert_target(&self, forum_id: ChatId, waiting: &Waiting) -> (ChatId, ThreadId) {
+        match self.alerts_thread {
+            Some(alerts_thread) if waiting.forum_id == forum_id.0 => (forum_id, alerts_thread),
+            _ => (ChatId(waiting.forum_id), ThreadId(MessageId(waiting.thread_id))),
+        }
+    }
+}
+
+/// Parses comma-separated thresholds like `15m,1h,4h`.
+pub fn parse_thresholds(input: &str) -> Option<Vec<Duration>> {
+    let mut thresholds = input.split(',')
+        .map(|threshold| parse_duration(threshold.trim()))
+        .collect::<Option<Vec<_>>>()?;
+    thresholds.sort();
+    thresholds.dedup();
+    Some(thresholds)
+}
+
+/// Checks the waiting users every `interval` until `shutdown` is cancelled.
+pub async fn watch(
+    bot: Bot,
+    db: Database,
+    forum_id: ChatId,
+    policy: SlaPolicy,
+    interval: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {
+                if let Err(e) = check(&bot, &db, forum_id, &policy).await {
+                    tracing::error!("Failed to check unanswered messages: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Sends an alert for every user of every forum that passed the next threshold.
+/// Steps missed while the bot was down are merged into one alert.
+async fn check(bot: &Bot, db: &Database, forum_id: ChatId, policy: &SlaPolicy) -> errors::Result<()> {
+    let now = unix_now();
+    for waiting in db.get_waiting().await? {
+        let waited = Duration::from_secs((now - waiting.since).max(0) as u64);
+        let escalation = policy.escalation(waited);
+        if escalation <= waiting.escalation {
+            continue;
+        }
+        let (chat_id, thread_id) = policy.alert_target(forum_id, &waiting);
+        // A deleted topic must not block the alerts about other users
+        if let Err(e) = bot.send_message(chat_id, alert_text(policy, &waiting, waited, escalation))
+            .message_thread_id(thread_id)
+            .await
+        {
+            tracing::warn!("Failed to send unanswered message alert for {}: {e}", waiting.thread_id);
+            continue;
+        }
+        db.set_escalation(waiting.private_chat, escalation).await?;
+        tracing::info!("Unanswered message alert {escalation} sent: {}", waiting.thread_id);
+    }
+
+    Ok(())
+}
+
+fn alert_text(policy: &SlaPolicy, waiting: &Waiting, waited: Duration, escalation: u32) -> String {
+    let link = topic_link(ChatId(waiting.forum_id), waiting.thread_id);
+    let text = format!(
+        "No reply for {} in <a href=\"{link}\">the topic</a>",
+        format_duration(Duration::from_secs(waited.as_secs() / 60 * 60)),
+    );
+    match &policy.mentions {
+        Some(mentions) if policy.is_last(escalation) => format!("🚨 {}, {text}", html::escape(mentions)),
+        _ if policy.is_last(escalation) => format!("🚨 {text}"),
+        _ => format!("⚠️ {text}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(mentions: Option<&str>) -> SlaPolicy {
+        SlaPolicy {
+            thresholds: parse_thresholds("1h, 15m,4h").unwrap(),
+            alerts_thread: None,
+            mentions: mentions.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_thresholds() {
+        assert_eq!(
+            parse_thresholds("1h, 15m,4h"),
+            Some(vec![Duration::from_secs(15 * 60), Duration::from_secs(60 * 60), Duration::from_secs(4 * 60 * 60)]),
+        );
+        assert_eq!(parse_thresholds("15m,soon"), None);
+        assert_eq!(parse_thresholds(""), None);
+    }
+
+    #[test]
+    fn test_escalation() {
+        let policy = policy(None);
+        assert_eq!(policy.escalation(Duration::from_secs(60)), 0);
+        assert_eq!(policy.escalation(Duration::from_secs(15 * 60)), 1);
+        assert_eq!(policy.escalation(Duration::from_secs(2 * 60 * 60)), 2);
+        assert_eq!(policy.escalation(Duration::from_secs(24 * 60 * 60)), 3);
+    }
+
+    #[test]
+    fn test_alert_text() {
+        let policy = policy(Some("@alice @bob"));
+        let waiting = Waiting { private_chat: 1, forum_id: -100123456789, thread_id: 42, since: 0, escalation: 0 };
+
+        let first = alert_text(&policy, &waiting, Duration::from_secs(15 * 60 + 10), 1);
+        assert_eq!(first, "⚠️ No reply for 15m in <a href=\"https://t.me/c/123456789/42\">the topic</a>");
+        let last = alert_text(&policy, &waiting, Duration::from_secs(4 * 60 * 60), 3);
+        assert!(last.starts_with("🚨 @alice @bob, No reply for 4h"));
+    }
+
+    #[test]
+    fn test_alert_target() {
+        let policy = SlaPolicy { alerts_thread: Some(ThreadId(MessageId(7))), ..policy(None) };
+        let waiting = Waiting { private_chat: 1, forum_id: -100, thread_id: 42, since: 0, escalation: 0 };
+
+        assert_eq!(policy.alert_target(ChatId(-100), &waiting), (ChatId(-100), ThreadId(MessageId(7))));
+        // The alerts thread is a topic of the default forum, admins of other forums see the alert in the user's topic
+        let other_forum = Waiting { forum_id: -200, ..waiting };
+        assert_eq!(policy.alert_target(ChatId(-100), &other_forum), (ChatId(-200), ThreadId(MessageId(42))));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::db::{Event, Stats, Totals};
+use crate::utils::{format_duration, parse_duration};
+use chrono::DateTime;
+use std::time::Duration;
+use teloxide::utils::html;
+
+/// Period of `/stats` without arguments.
+const DEFAULT_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// The statistics are limited to a year.
+const MAX_PERIOD: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+/// Only the most recent days with new topics are listed, so that the text fits into a message.
+const MAX_DAYS: usize = 31;
+/// Only the most popular sources are listed.
+const MAX_SOURCES: usize = 20;
+
+/// Parses the optional period of `/stats`, f.e. `30d`.
+pub fn parse_period(args: &str) -> Option<Duration> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Some(DEFAULT_PERIOD);
+    }
+    parse_duration(args).filter(|period| *period <= MAX_PERIOD)
+}
+
+/// Formats the statistics for `/stats`.
+///
+/// # Arguments
+///
+/// * `period` - The period the statistics were collected for.
+/// * `new_topics` - Number of new topics per day, by the number of the day since the unix epoch, oldest first.
+/// * `sources` - Number of users per `/start` payload, most popular first.
+pub fn stats_text(
+    period: Duration,
+    totals: Totals,
+    stats: &Stats,
+    new_topics: &[(i64, i64)],
+    sources: &[(String, i64)],
+) -> String {
+    let mut text = format!(
+        "📈 <b>Statistics for {}</b>\
+        \n\n💬 Active topics: {} \
+        \n🚫 Banned users: {} \
+        \n📥 Messages in: {} \
+        \n📤 Messages out: {}",
+        format_duration(period),
+        totals.active_mappings,
+        totals.banned,
+        stats.count(Event::MessageIn),
+        stats.count(Event::MessageOut),
+    );
+    if !new_topics.is_empty() {
+        text.push_str("\n\n🆕 New topics per day:");
+        let skipped = new_topics.len().saturating_sub(MAX_DAYS);
+        if skipped > 0 {
+            text.push_str(&format!("\n• … {skipped} earlier days"));
+        }
+        for (day, count) in &new_topics[skipped..] {
+            let date = DateTime::from_timestamp(day * 24 * 60 * 60, 0)
+                .map(|date| date.date_naive().to_string())
+                .unwrap_or_else(|| day.to_string());
+            text.push_str(&format!("\n• {date}: {count}"));
+        }
+    }
+    if !sources.is_empty() {
+        text.push_str("\n\n🔗 Sources:");
+        for (source, count) in sources.iter().take(MAX_SOURCES) {
+            text.push_str(&format!("\n• <code>{}</code>: {count}", html::escape(source)));
+        }
+        if sources.len() > MAX_SOURCES {
+            text.push_str(&format!("\n• … {} more", sources.len() - MAX_SOURCES));
+        }
+    }
+    text.push_str("\n\n⏱ First response: ");
+    if stats.response_times.is_empty() {
+        text.push('—');
+    } else {
+        let percentiles = [50, 90, 99]
+            .map(|p| {
+                let secs = stats.response_percentile(p).unwrap_or_default();
+                format!("p{p} {}", format_duration(Duration::from_secs(secs as u64)))
+            });
+        text.push_str(&percentiles.join(" · "));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_period() {
+        assert_eq!(parse_period(""), Some(DEFAULT_PERIOD));
+        assert_eq!(parse_period(" 30d "), Some(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(parse_period("month"), None);
+        assert_eq!(parse_period("9223372036000000000s"), None);
+    }
+
+    #[test]
+    fn test_stats_text() {
+        let totals = Totals { active_mappings: 12, banned: 3 };
+        let stats = Stats {
+            events: [(Event::MessageIn, 40), (Event::MessageOut, 35)].into(),
+            response_times: vec![60, 120, 180, 240, 3600],
+        };
+
+        let text = stats_text(DEFAULT_PERIOD, totals, &stats, &[(19_725, 4)], &[("ads".to_string(), 2)]);
+        assert!(text.starts_with("📈 <b>Statistics for 7d</b>"));
+        assert!(text.contains("💬 Active topics: 12"));
+        assert!(text.contains("📤 Messages out: 35"));
+        assert!(text.contains("• 2024-01-03: 4"));
+        assert!(text.contains("🔗 Sources:\n• <code>ads</code>: 2"));
+        assert!(text.ends_with("⏱ First response: p50 3m · p90 1h · p99 1h"));
+
+        let days = (19_000..19_365).map(|day| (day, 1)).collect::<Vec<_>>();
+        let sources = (0..100).map(|i| (format!("source_{i}"), 1)).collect::<Vec<_>>();
+        let text = stats_text(MAX_PERIOD, totals, &stats, &days, &sources);
+        assert!(text.contains("• … 334 earlier days"));
+        assert!(text.contains("• … 80 more"));
+        assert!(text.chars().count() < 4096);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::config::{LogFormat, Settings};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+use url::Url;
+
+const SERVICE_NAME: &str = "panopticonbot";
+const DEFAULT_FILTER: &str = "info";
+
+/// Keeps the OTLP exporter alive, flushes the remaining spans when dropped.
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to shutdown the OTLP exporter: {e}");
+            }
+        }
+    }
+}
+
+/// Installs the global subscriber:
+/// `RUST_LOG`-style filtering, pretty or JSON output and optional OTLP span export.
+pub fn init_tracing(settings: &Settings) -> Result<TelemetryGuard, Box<dyn std::error::Error>> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let fmt_layer = match settings.log_format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+    let provider = settings.otlp_endpoint
+        .as_ref()
+        .map(otlp_tracer_provider)
+        .transpose()?;
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .try_init()?;
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        tracing::info!("Exporting traces to: {endpoint}");
+    }
+    Ok(TelemetryGuard { provider })
+}
+
+/// Creates a tracer provider that sends spans to `{endpoint}/v1/traces` over OTLP/HTTP.
+fn otlp_tracer_provider(endpoint: &Url) -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(traces_url(endpoint)?.as_str())
+        .build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+    Ok(provider)
+}
+
+/// Appends `v1/traces` to the path of the endpoint, a collector may be behind a prefix like `/otlp`.
+fn traces_url(endpoint: &Url) -> Result<Url, url::ParseError> {
+    let mut endpoint = endpoint.clone();
+    if !endpoint.path().ends_with('/') {
+        endpoint.set_path(&format!("{}/", endpoint.path()));
+    }
+    endpoint.join("v1/traces")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /// Accepts a single OTLP/HTTP request and reports its request line.
+    fn collector_stand_in() -> (Url, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/otlp", listener.local_addr().unwrap())).unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() { break; }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            reader.get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            tx.send(request_line.trim().to_string()).unwrap();
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn test_otlp_export() {
+        let (endpoint, requests) = collector_stand_in();
+        let provider = otlp_tracer_provider(&endpoint).expect("Failed to build the exporter");
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("Private chat handler", user_id = 1).in_scope(|| {
+                tracing::info!("Relayed");
+            });
+        });
+        provider.force_flush().expect("Failed to flush spans");
+
+        let request_line = requests.recv_timeout(Duration::from_secs(5))
+            .expect("Collector received nothing");
+        assert_eq!(request_line, "POST /otlp/v1/traces HTTP/1.1");
+        provider.shutdown().expect("Failed to shutdown the exporter");
+
+        let traces = |endpoint: &str| traces_url(&Url::parse(endpoint).unwrap()).unwrap().to_string();
+        assert_eq!(traces("http://collector:4318"), "http://collector:4318/v1/traces");
+        assert_eq!(traces("http://collector/otlp/"), "http://collector/otlp/v1/traces");
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use teloxide::types::ChatId;
+
+/// Parses durations like `90s`, `15m`, `2h`, `1d` or `1h30m`.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        let value: u64 = number.parse().ok()?;
+        total = total.checked_add(value.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if !number.is_empty() || total == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(total))
+}
+
+/// Formats the duration the same way `parse_duration` reads it, f.e. `1h 30m`.
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    let mut parts = Vec::new();
+    for (unit, name) in [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m"), (1, "s")] {
+        if secs >= unit {
+            parts.push(format!("{}{name}", secs / unit));
+            secs %= unit;
+        }
+    }
+    if parts.is_empty() {
+        return "0s".to_string();
+    }
+    parts.join(" ")
+}
+
+/// Returns the current unix timestamp in seconds.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Formats the unix timestamp as UTC date and time, f.e. `2024-01-03 12:00 UTC`.
+pub fn format_date(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Returns a link to the topic in the forum, f.e. `https://t.me/c/123456789/42`.
+/// Forum ids have the `-100` prefix, which is not a part of the link.
+pub fn topic_link(forum_id: ChatId, thread_id: i32) -> String {
+    let internal_id = forum_id.0.to_string()
+        .trim_start_matches("-100")
+        .to_string();
+    format!("https://t.me/c/{internal_id}/{thread_id}")
+}
+
+/// Returns a link to the message in the topic, f.e. `https://t.me/c/123456789/42/100`.
+pub fn message_link(forum_id: ChatId, thread_id: i32, message_id: i32) -> String {
+    format!("{}/{message_id}", topic_link(forum_id, thread_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(24 * 60 * 60)));
+        assert_eq!(parse_duration("2"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("0m"), None);
+        assert_eq!(parse_duration("2w"), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(90 * 60)), "1h 30m");
+        assert_eq!(format_duration(Duration::from_secs(24 * 60 * 60 + 5)), "1d 5s");
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(1_704_283_200), "2024-01-03 12:00 UTC");
+    }
+
+    #[test]
+    fn test_topic_link() {
+        assert_eq!(topic_link(ChatId(-100123456789), 42), "https://t.me/c/123456789/42");
+        assert_eq!(message_link(ChatId(-100123456789), 42, 100), "https://t.me/c/123456789/42/100");
+    }
+}